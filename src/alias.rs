@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+
+// Global options which take a value, whose values have to be skipped to find the command being run.
+const GLOBAL_OPTIONS: [&str; 3] = ["--format", "--file", "--list"];
+
+fn command_position(args: &[String]) -> Option<usize> {
+    // Finds the first argument which isn't a global option, and so names a command or an alias.
+    let mut i = 1;
+    while i < args.len() {
+        if !args[i].starts_with('-') {
+            return Some(i);
+        }
+        if GLOBAL_OPTIONS.contains(&args[i].as_str()) {
+            i += 1;
+        }
+        i += 1;
+    }
+    None
+}
+
+pub fn split(line: &str) -> Result<Vec<String>, String> {
+    // Splits a command line into words like a shell does, so that quotes group words together and backslashes escape the next character.
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                word.extend(chars.next());
+                in_word = true;
+            },
+            (Some(_), c) => word.push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                in_word = true;
+            },
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            },
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("\"{}\" has an unclosed quote.", line));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+fn substitute(name: &str, expansion: &str, args: &[String]) -> Result<Vec<String>, String> {
+    // Replaces $1 to $9 with the arguments given after an alias, and $@ with all of them. Arguments which aren't used this way are added to the end of the expansion, so that an alias without any placeholders acts as a prefix.
+    let mut used = 0;
+    let mut all = false;
+    let mut words = Vec::new();
+    for word in split(expansion).map_err(|err| format!("The alias {} can't be used: {}", name, err))? {
+        if word == "$@" {
+            all = true;
+            words.extend(args.iter().cloned());
+            continue;
+        }
+        let mut result = String::new();
+        let mut chars = word.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('$', Some('@')) => {
+                    chars.next();
+                    all = true;
+                    result.push_str(&args.join(" "));
+                },
+                ('$', Some(d)) if d.is_ascii_digit() && *d != '0' => {
+                    let n = d.to_digit(10).unwrap() as usize;
+                    chars.next();
+                    let arg = args.get(n - 1).ok_or_else(|| {
+                        format!("The alias {} needs at least {} argument(s), but was given {}.", name, n, args.len())
+                    })?;
+                    used = used.max(n);
+                    result.push_str(arg);
+                },
+                (c, _) => result.push(c)
+            }
+        }
+        words.push(result);
+    }
+    if !all {
+        words.extend(args[used..].iter().cloned());
+    }
+    Ok(words)
+}
+
+pub fn expand(args: Vec<String>, aliases: &BTreeMap<String, String>) -> Result<Vec<String>, String> {
+    // Expands the alias named in place of a command, repeatedly, since an alias may expand to another alias. Aliases which are named after commands are never expanded, as commands take precedence.
+    let mut args = args;
+    let mut seen: Vec<String> = Vec::new();
+    while let Some(position) = command_position(&args) {
+        let name = args[position].clone();
+        let expansion = match aliases.get(&name).filter(|_| !crate::is_command(&name)) {
+            Some(expansion) => expansion,
+            None => break
+        };
+        if seen.contains(&name) {
+            seen.push(name);
+            return Err(format!("The alias {} expands to itself ({}).", seen.last().unwrap(), seen.join(" -> ")));
+        }
+        let mut expanded = args[..position].to_vec();
+        expanded.append(&mut substitute(&name, expansion, &args[position + 1..])?);
+        args = expanded;
+        seen.push(name);
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        split(line).unwrap()
+    }
+
+    fn aliases(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(name, expansion)| (String::from(*name), String::from(*expansion))).collect()
+    }
+
+    fn expanded(line: &str, aliases: &BTreeMap<String, String>) -> Result<Vec<String>, String> {
+        expand(words(&format!("faros {}", line)), aliases)
+    }
+
+    #[test]
+    fn quoted_words_round_trip() {
+        let original = ["plain", "two words", "", "it's", "say \"hi\"", "back\\slash", "tab\there"];
+        let line = original.iter()
+                           .map(|word| format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\"")))
+                           .collect::<Vec<_>>()
+                           .join(" ");
+        assert_eq!(words(&line), original);
+        assert_eq!(words(&original[..2].join(" ")), ["plain", "two", "words"]);
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(words("  add task  -n 'Buy milk' "), ["add", "task", "-n", "Buy milk"]);
+        assert_eq!(words("a\\ b 'c\\d' \"e\\\"f\" ''"), ["a b", "c\\d", "e\"f", ""]);
+        assert_eq!(words("pre'fix'ed"), ["prefixed"]);
+        assert!(words("").is_empty());
+    }
+
+    #[test]
+    fn unclosed_quotes() {
+        assert!(split("add task -n 'Buy milk").is_err());
+        assert!(split("add task -n \"Buy milk").is_err());
+        assert!(split("'\"'").is_ok());
+    }
+
+    #[test]
+    fn expansion() {
+        let aliases = aliases(&[("t", "add task -n"), ("due", "add task -n $1 -D $2"), ("both", "t $@"), ("call", "add task -n \"Call $@\""), ("list", "tree")]);
+        assert_eq!(expanded("t Milk -p high", &aliases).unwrap(), ["faros", "add", "task", "-n", "Milk", "-p", "high"]);
+        assert_eq!(expanded("--list home due Milk 3 -p low", &aliases).unwrap(),
+                   ["faros", "--list", "home", "add", "task", "-n", "Milk", "-D", "3", "-p", "low"]);
+        assert_eq!(expanded("both 'Buy milk' -p high", &aliases).unwrap(), ["faros", "add", "task", "-n", "Buy milk", "-p", "high"]);
+        assert_eq!(expanded("call Mum now", &aliases).unwrap(), ["faros", "add", "task", "-n", "Call Mum now"]);
+        assert_eq!(expanded("list", &aliases).unwrap(), ["faros", "list"]);
+    }
+
+    #[test]
+    fn malformed_aliases() {
+        let aliases = aliases(&[("due", "add task -n $1 -D $2"), ("a", "b"), ("b", "a"), ("open", "add task -n 'x")]);
+        assert!(expanded("due Milk", &aliases).unwrap_err().contains("at least 2"));
+        assert_eq!(expanded("a", &aliases).unwrap_err(), "The alias a expands to itself (a -> b -> a).");
+        assert!(expanded("open", &aliases).is_err());
+    }
+}
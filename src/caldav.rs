@@ -0,0 +1,825 @@
+use base64::Engine;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+use crate::TODOList;
+use crate::config::{CalDav, Policy};
+use crate::ical::{self, Vtodo};
+
+const QUERY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+  <d:prop>
+    <d:getetag/>
+    <c:calendar-data/>
+  </d:prop>
+  <c:filter>
+    <c:comp-filter name="VCALENDAR">
+      <c:comp-filter name="VTODO"/>
+    </c:comp-filter>
+  </c:filter>
+</c:calendar-query>
+"#;
+
+pub struct Resource {
+    pub href: String,
+    pub etag: String,
+    pub data: String
+}
+
+pub enum Condition<'a> {
+    Create,
+    Match(&'a str)
+}
+
+pub enum Put {
+    Stored(Option<String>),
+    Conflict
+}
+
+pub trait Collection {
+    // A collection of calendar resources, each holding one VTODO. This is a CalDAV server in practice, but the sync itself only relies on this trait, so it can be run against a stand-in.
+    fn list(&self) -> Result<Vec<Resource>, String>;
+    fn put(&self, href: &str, data: &str, condition: Condition) -> Result<Put, String>;
+    fn delete(&self, href: &str, etag: &str) -> Result<bool, String>;
+    fn href(&self, uuid: Uuid) -> Result<String, String>;
+}
+
+pub struct Server {
+    agent: ureq::Agent,
+    url: url::Url,
+    authorization: Option<String>
+}
+
+impl Server {
+    pub fn new(config: &CalDav) -> Result<Server, String> {
+        // The collection URL is treated as a directory, so that resources can be named relative to it. The password may come from the configuration file or from $FAROS_CALDAV_PASSWORD.
+        let mut url = config.url.clone();
+        if !url.ends_with('/') {
+            url.push('/');
+        }
+        let url = url::Url::parse(&url).map_err(|err| format!("{} is not a valid URL: {}", config.url, err))?;
+        let password = config.password.clone().or_else(|| std::env::var("FAROS_CALDAV_PASSWORD").ok());
+        let authorization = config.username.as_ref().map(|username| {
+            let credentials = format!("{}:{}", username, password.unwrap_or_default());
+            format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(credentials))
+        });
+        Ok(Server { agent: ureq::AgentBuilder::new().build(), url, authorization })
+    }
+
+    fn request(&self, method: &str, href: &str) -> Result<ureq::Request, String> {
+        let url = self.url.join(href).map_err(|err| format!("{} is not a valid href: {}", href, err))?;
+        let request = self.agent.request_url(method, &url);
+        Ok(match &self.authorization {
+            Some(authorization) => request.set("Authorization", authorization),
+            None => request
+        })
+    }
+
+    fn send(&self, request: ureq::Request, body: &str) -> Result<Result<ureq::Response, u16>, String> {
+        // Returns the response to a request, or its status code if the server refused it. Only errors which aren't responses from the server, such as a missing connection, are returned as errors.
+        match request.send_string(body) {
+            Ok(response) => Ok(Ok(response)),
+            Err(ureq::Error::Status(code, _)) => Ok(Err(code)),
+            Err(err) => Err(format!("{} couldn't be reached: {}", self.url, err))
+        }
+    }
+}
+
+impl Collection for Server {
+    fn list(&self) -> Result<Vec<Resource>, String> {
+        let request = self.request("REPORT", "")?
+                          .set("Depth", "1")
+                          .set("Content-Type", "application/xml; charset=utf-8");
+        let response = self.send(request, QUERY)?.map_err(|code| format!("{} refused to list its tasks, with status {}.", self.url, code))?;
+        let body = response.into_string().map_err(|err| format!("The response from {} couldn't be read: {}", self.url, err))?;
+        parse_multistatus(&body)
+    }
+
+    fn put(&self, href: &str, data: &str, condition: Condition) -> Result<Put, String> {
+        let request = self.request("PUT", href)?.set("Content-Type", "text/calendar; charset=utf-8");
+        let request = match condition {
+            Condition::Create => request.set("If-None-Match", "*"),
+            Condition::Match(etag) => request.set("If-Match", etag)
+        };
+        match self.send(request, data)? {
+            Ok(response) => Ok(Put::Stored(response.header("ETag").map(String::from))),
+            Err(412) => Ok(Put::Conflict),
+            Err(code) => Err(format!("{} refused to store {}, with status {}.", self.url, href, code))
+        }
+    }
+
+    fn delete(&self, href: &str, etag: &str) -> Result<bool, String> {
+        match self.send(self.request("DELETE", href)?.set("If-Match", etag), "")? {
+            Ok(_) | Err(404) => Ok(true),
+            Err(412) => Ok(false),
+            Err(code) => Err(format!("{} refused to delete {}, with status {}.", self.url, href, code))
+        }
+    }
+
+    fn href(&self, uuid: Uuid) -> Result<String, String> {
+        self.url.join(&format!("{}.ics", uuid)).map(|url| String::from(url.path())).map_err(|err| format!("{} can't hold a task: {}", self.url, err))
+    }
+}
+
+fn parse_multistatus(body: &str) -> Result<Vec<Resource>, String> {
+    // Reads the href, ETag and calendar data of each resource in a WebDAV multistatus response. Resources without calendar data, such as the collection itself, are left out.
+    let document = roxmltree::Document::parse(body).map_err(|err| format!("The server's response couldn't be parsed: {}", err))?;
+    let find = |node: roxmltree::Node, namespace: &str, name: &str| {
+        node.descendants()
+            .find(|n| n.tag_name().name() == name && n.tag_name().namespace() == Some(namespace))
+            .and_then(|n| n.text())
+            .map(|text| String::from(text.trim()))
+    };
+    Ok(document.descendants()
+               .filter(|node| node.tag_name().name() == "response" && node.tag_name().namespace() == Some("DAV:"))
+               .filter_map(|response| Some(Resource {
+                   href: find(response, "DAV:", "href")?,
+                   etag: find(response, "DAV:", "getetag")?,
+                   data: find(response, "urn:ietf:params:xml:ns:caldav", "calendar-data")?
+               }))
+               .collect())
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct State {
+    collection: String,
+    tasks: HashMap<Uuid, Synced>
+}
+
+#[derive(Serialize, Deserialize)]
+struct Synced {
+    href: String,
+    etag: Option<String>,
+    snapshot: String,
+    synced: DateTime<Local>
+}
+
+impl State {
+    pub fn read(collection: &str, list: &Path) -> State {
+        // Reads what was known about each task when it was last synced, which is kept next to the TODO list, so that list.json has list.caldav.json. The state is thrown away if it belongs to a different collection.
+        let state = fs::read_to_string(list.with_extension("caldav.json"))
+            .ok()
+            .and_then(|contents| serde_json::from_str::<State>(&contents).ok())
+            .filter(|state| state.collection == collection);
+        state.unwrap_or_else(|| State { collection: String::from(collection), tasks: HashMap::new() })
+    }
+
+    pub fn write(&self, list: &Path) -> Result<(), String> {
+        let path = list.with_extension("caldav.json");
+        fs::write(&path, serde_json::to_string(self).unwrap()).map_err(|_| format!("{} could not be written to.", path.display()))
+    }
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub pushed: usize,
+    pub pulled: usize,
+    pub deleted_locally: usize,
+    pub deleted_remotely: usize,
+    pub conflicts: Vec<String>,
+    pub problems: Vec<String>,
+    // Why the sync stopped before sending every change to the server. What was synced before then is still recorded, so that the next sync carries on from there rather than seeing its own changes as conflicts.
+    pub interrupted: Option<String>
+}
+
+enum Action {
+    Push,
+    Pull,
+    DeleteLocal,
+    DeleteRemote,
+    Forget,
+    Conflict(&'static str)
+}
+
+fn snapshot(uuid: Uuid, todo_list: &TODOList) -> Option<String> {
+    // What a task would look like on the server, minus its timestamp, so that it can be compared with what it looked like when it was last synced.
+    let task = todo_list.flattened().into_iter().find(|task| task.uuid == uuid)?;
+    Some(ical::vtodo(task, todo_list).split("\r\n").filter(|line| !line.starts_with("DTSTAMP:")).collect::<Vec<_>>().join("\r\n"))
+}
+
+fn move_task(todo_list: &mut TODOList, uuid: Uuid, parent: Option<Uuid>) {
+    // Moves a task and its subtasks beneath another task, or to the top level, as long as that doesn't put the task beneath itself.
+    let path = todo_list.path(uuid).iter().map(|task| task.uuid).collect::<Vec<_>>();
+    let parent = parent.filter(|&p| !todo_list.path(p).is_empty() && !todo_list.path(p).iter().any(|task| task.uuid == uuid));
+    if path.len().checked_sub(2).map(|i| path[i]) == parent {
+        return;
+    }
+    let task = todo_list.task_from_uuid(uuid).clone();
+    todo_list.remove_uuid(uuid);
+    match parent {
+        Some(parent) => todo_list.task_from_uuid(parent).add_subtask(task),
+        None => todo_list.tasks.push(task)
+    }
+}
+
+pub fn sync(todo_list: &mut TODOList, collection: &dyn Collection, state: &mut State, policy: Policy) -> Result<Report, String> {
+    // Brings the TODO list and the collection into line with each other. Each task is compared with what it looked like when it was last synced, on each side: a change on one side is copied to the other, and a change on both sides is a conflict, which is settled by the policy. Manual conflicts are left alone, and come up again on the next sync.
+    let mut report = Report::default();
+    let mut remote: HashMap<Uuid, (Resource, Vtodo)> = HashMap::new();
+    let mut order = todo_list.flattened().iter().map(|task| task.uuid).collect::<Vec<_>>();
+    for resource in collection.list()? {
+        match ical::parse(&resource.data).map(|vtodos| vtodos.into_iter().next()) {
+            Ok(Some(vtodo)) if vtodo.status == "CANCELLED" => (),
+            Ok(Some(vtodo)) => {
+                order.push(vtodo.uuid);
+                remote.insert(vtodo.uuid, (resource, vtodo));
+            },
+            Ok(None) => report.problems.push(format!("{}: there is no VTODO in it, so it was skipped", resource.href)),
+            Err(err) => report.problems.push(format!("{}: {}", resource.href, err))
+        }
+    }
+    order.extend(state.tasks.keys().copied());
+    let mut seen = HashSet::new();
+    order.retain(|uuid| seen.insert(*uuid));
+
+    let mut actions = Vec::new();
+    for uuid in order {
+        let local = snapshot(uuid, todo_list);
+        let known = state.tasks.get(&uuid);
+        let resource = remote.get(&uuid).map(|(resource, _)| resource);
+        let local_changed = known.is_none_or(|known| local.as_ref() != Some(&known.snapshot));
+        let remote_changed = known.is_none_or(|known| resource.is_none_or(|r| known.etag.as_ref() != Some(&r.etag)));
+        let action = match (local.is_some(), known.is_some(), resource.is_some()) {
+            (true, true, true) => match (local_changed, remote_changed) {
+                (false, false) => continue,
+                (true, false) => Action::Push,
+                (false, true) => Action::Pull,
+                (true, true) => Action::Conflict("it was changed both here and on the server")
+            },
+            (true, true, false) if local_changed => Action::Conflict("it was changed here, but deleted from the server"),
+            (true, true, false) => Action::DeleteLocal,
+            (false, true, true) if remote_changed => Action::Conflict("it was changed on the server, but deleted here"),
+            (false, true, true) => Action::DeleteRemote,
+            (false, true, false) => Action::Forget,
+            (true, false, false) => Action::Push,
+            (false, false, true) => Action::Pull,
+            (true, false, true) => Action::Conflict("it was added both here and on the server"),
+            (false, false, false) => continue
+        };
+        let action = match (action, policy) {
+            (Action::Conflict(_), Policy::Local) if local.is_some() => Action::Push,
+            (Action::Conflict(_), Policy::Local) => Action::DeleteRemote,
+            (Action::Conflict(_), Policy::Remote) if resource.is_some() => Action::Pull,
+            (Action::Conflict(_), Policy::Remote) => Action::DeleteLocal,
+            (action, _) => action
+        };
+        actions.push((uuid, action));
+    }
+
+    // Changes from the server are made first, so that what is pushed afterwards includes them.
+    let now = Local::now();
+    let pulls = actions.iter().filter(|(_, action)| matches!(action, Action::Pull)).map(|(uuid, _)| *uuid).collect::<Vec<_>>();
+    let vtodos = pulls.iter().map(|uuid| remote[uuid].1.clone()).collect::<Vec<_>>();
+    let parents = vtodos.iter().map(|vtodo| (vtodo.uuid, vtodo.parent)).collect::<Vec<_>>();
+    report.problems.extend(ical::import_vtodos(vtodos, todo_list));
+    for (uuid, parent) in parents {
+        if !todo_list.path(uuid).is_empty() {
+            move_task(todo_list, uuid, parent);
+        }
+    }
+    for uuid in pulls {
+        let (resource, _) = &remote[&uuid];
+        state.tasks.insert(uuid, Synced {
+            href: resource.href.clone(),
+            etag: Some(resource.etag.clone()),
+            snapshot: snapshot(uuid, todo_list).unwrap_or_default(),
+            synced: now
+        });
+        report.pulled += 1;
+    }
+
+    for (uuid, action) in &actions {
+        let uuid = *uuid;
+        match action {
+            Action::DeleteLocal => {
+                // Subtasks which are still on the server are kept, as top-level tasks.
+                if let Some(task) = todo_list.task_with_uuid(uuid).map(|task| task.clone()) {
+                    todo_list.remove_uuid(uuid);
+                    todo_list.tasks.extend(task.children().iter().cloned());
+                }
+                state.tasks.remove(&uuid);
+                report.deleted_locally += 1;
+            },
+            Action::DeleteRemote => {
+                let (href, etag) = match remote.get(&uuid) {
+                    Some((resource, _)) if report.interrupted.is_none() => (resource.href.clone(), resource.etag.clone()),
+                    _ => continue
+                };
+                match collection.delete(&href, &etag) {
+                    Ok(true) => {
+                        state.tasks.remove(&uuid);
+                        report.deleted_remotely += 1;
+                    },
+                    Ok(false) => report.conflicts.push(format!("{}: it was changed on the server during the sync, so it wasn't deleted", remote[&uuid].1.summary)),
+                    Err(err) => report.interrupted = Some(err)
+                }
+            },
+            Action::Forget => {
+                state.tasks.remove(&uuid);
+            },
+            Action::Conflict(reason) => {
+                let name = todo_list.task_with_uuid(uuid).map_or_else(|| remote[&uuid].1.summary.clone(), |task| task.name.clone());
+                report.conflicts.push(format!("{}: {}", name, reason));
+            },
+            Action::Push | Action::Pull => ()
+        }
+    }
+
+    for (uuid, action) in &actions {
+        if !matches!(action, Action::Push) || report.interrupted.is_some() {
+            continue;
+        }
+        let task = match todo_list.flattened().into_iter().find(|task| task.uuid == *uuid) {
+            Some(task) => task,
+            None => continue
+        };
+        let data = ical::calendar(&[ical::vtodo(task, todo_list)]);
+        let sent = (match (remote.get(uuid), state.tasks.get(uuid)) {
+            (Some((resource, _)), _) => Ok((resource.href.clone(), Condition::Match(&resource.etag))),
+            (None, Some(known)) => Ok((known.href.clone(), Condition::Create)),
+            (None, None) => collection.href(*uuid).map(|href| (href, Condition::Create))
+        }).and_then(|(href, condition)| {
+            let snapshot = snapshot(*uuid, todo_list).ok_or_else(|| format!("{} couldn't be found in your TODO list to be sent.", task.name))?;
+            Ok((collection.put(&href, &data, condition)?, href, snapshot))
+        });
+        match sent {
+            Ok((Put::Stored(etag), href, snapshot)) => {
+                state.tasks.insert(*uuid, Synced { href, etag, snapshot, synced: now });
+                report.pushed += 1;
+            },
+            Ok((Put::Conflict, _, _)) => report.conflicts.push(format!("{}: it was changed on the server during the sync, so it wasn't sent", task.name)),
+            Err(err) => report.interrupted = Some(err)
+        }
+    }
+
+    // Servers don't have to return the ETag of a stored resource, in which case it is read from the collection instead. If that fails, the ETags are left unknown, and the tasks are fetched again on the next sync.
+    if report.interrupted.is_none() && state.tasks.values().any(|known| known.etag.is_none()) {
+        match collection.list() {
+            Ok(resources) => {
+                let etags = resources.into_iter().map(|resource| (resource.href, resource.etag)).collect::<HashMap<_, _>>();
+                for known in state.tasks.values_mut().filter(|known| known.etag.is_none()) {
+                    known.etag = etags.get(&known.href).cloned();
+                }
+            },
+            Err(err) => report.interrupted = Some(err)
+        }
+    }
+    for known in state.tasks.values_mut() {
+        known.synced = now;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::collections::BTreeMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use crate::{Completion, Priority, Task};
+
+    // A stand-in for a CalDAV server, which keeps its resources in memory and gives each version of a resource a new ETag, refusing conditional requests whose ETags don't match as a server would with 412 Precondition Failed.
+    #[derive(Default)]
+    struct Memory {
+        resources: RefCell<BTreeMap<String, (String, String)>>,
+        etags: Cell<usize>,
+        // When set, every resource is changed right after it is listed, as if someone else changed it during the sync.
+        meddle: Cell<bool>,
+        // When set, this many more changes are made before the collection can no longer be reached, as if the connection was lost during the sync.
+        failing: Cell<Option<usize>>
+    }
+
+    impl Memory {
+        fn reach(&self) -> Result<(), String> {
+            match self.failing.get() {
+                Some(0) => Err(String::from("the collection couldn't be reached")),
+                Some(n) => {
+                    self.failing.set(Some(n - 1));
+                    Ok(())
+                },
+                None => Ok(())
+            }
+        }
+
+        fn store(&self, href: &str, data: &str) -> String {
+            self.etags.set(self.etags.get() + 1);
+            let etag = format!("\"{}\"", self.etags.get());
+            self.resources.borrow_mut().insert(String::from(href), (etag.clone(), String::from(data)));
+            etag
+        }
+
+        fn vtodo(&self, uuid: Uuid) -> Option<Vtodo> {
+            let resources = self.resources.borrow();
+            let (_, data) = resources.get(&self.href(uuid).unwrap())?;
+            ical::parse(data).unwrap().into_iter().next()
+        }
+
+        fn edit(&self, uuid: Uuid, from: &str, to: &str) {
+            let href = self.href(uuid).unwrap();
+            let data = self.resources.borrow()[&href].1.replace(from, to);
+            self.store(&href, &data);
+        }
+    }
+
+    impl Collection for Memory {
+        fn list(&self) -> Result<Vec<Resource>, String> {
+            let resources = self.resources
+                                .borrow()
+                                .iter()
+                                .map(|(href, (etag, data))| Resource { href: href.clone(), etag: etag.clone(), data: data.clone() })
+                                .collect();
+            if self.meddle.get() {
+                let listed = self.resources.borrow().iter().map(|(href, (_, data))| (href.clone(), data.clone())).collect::<Vec<_>>();
+                for (href, data) in listed {
+                    self.store(&href, &data);
+                }
+            }
+            Ok(resources)
+        }
+
+        fn put(&self, href: &str, data: &str, condition: Condition) -> Result<Put, String> {
+            self.reach()?;
+            let current = self.resources.borrow().get(href).map(|(etag, _)| etag.clone());
+            match (condition, current) {
+                (Condition::Create, Some(_)) => return Ok(Put::Conflict),
+                (Condition::Match(etag), current) if current.as_deref() != Some(etag) => return Ok(Put::Conflict),
+                _ => ()
+            }
+            Ok(Put::Stored(Some(self.store(href, data))))
+        }
+
+        fn delete(&self, href: &str, etag: &str) -> Result<bool, String> {
+            self.reach()?;
+            let current = self.resources.borrow().get(href).map(|(etag, _)| etag.clone());
+            match current {
+                Some(current) if current != etag => Ok(false),
+                _ => {
+                    self.resources.borrow_mut().remove(href);
+                    Ok(true)
+                }
+            }
+        }
+
+        fn href(&self, uuid: Uuid) -> Result<String, String> {
+            Ok(format!("/calendars/faros/{}.ics", uuid))
+        }
+    }
+
+    fn task(name: &str) -> Task {
+        Task::new(String::from(name), String::new(), Priority::Medium, crate::default_due_date())
+    }
+
+    fn list(tasks: Vec<Task>) -> TODOList {
+        TODOList { tasks, tags: Vec::new() }
+    }
+
+    fn state() -> State {
+        State { collection: String::from("memory"), tasks: HashMap::new() }
+    }
+
+    fn names(todo_list: &TODOList) -> Vec<&str> {
+        todo_list.flattened().iter().map(|task| task.name.as_str()).collect()
+    }
+
+    fn synced(tasks: Vec<Task>) -> (TODOList, Memory, State) {
+        // Returns a TODO list which has been synced once, along with the collection and the state of the sync.
+        let mut todo_list = list(tasks);
+        let memory = Memory::default();
+        let mut state = state();
+        let report = sync(&mut todo_list, &memory, &mut state, Policy::Manual).unwrap();
+        assert!(report.conflicts.is_empty() && report.problems.is_empty());
+        (todo_list, memory, state)
+    }
+
+    #[test]
+    fn push() {
+        let mut parent = task("Parent");
+        parent.add_subtask(task("Child"));
+        let (todo_list, memory, state) = synced(vec![parent]);
+        let (parent, child) = (todo_list.tasks[0].uuid, todo_list.tasks[0].children()[0].uuid);
+        assert_eq!(memory.resources.borrow().len(), 2);
+        assert_eq!(memory.vtodo(child).unwrap().parent, Some(parent));
+        assert!(state.tasks.values().all(|known| known.etag.is_some()));
+
+        // Nothing changed, so nothing is sent again.
+        let (mut todo_list, mut state) = (todo_list, state);
+        let report = sync(&mut todo_list, &memory, &mut state, Policy::Manual).unwrap();
+        assert_eq!((report.pushed, report.pulled), (0, 0));
+        assert_eq!(memory.etags.get(), 2);
+
+        todo_list.task_from_uuid(child).name = String::from("Renamed");
+        let report = sync(&mut todo_list, &memory, &mut state, Policy::Manual).unwrap();
+        assert_eq!((report.pushed, report.pulled), (1, 0));
+        assert_eq!(memory.vtodo(child).unwrap().summary, "Renamed");
+    }
+
+    #[test]
+    fn pull() {
+        let (mut todo_list, memory, mut state) = synced(vec![task("Ours")]);
+        let uuid = todo_list.tasks[0].uuid;
+        let theirs = list(vec![task("Theirs")]);
+        let data = ical::calendar(&[ical::vtodo(&theirs.tasks[0], &theirs)]);
+        memory.store(&memory.href(theirs.tasks[0].uuid).unwrap(), &data);
+        memory.edit(uuid, "SUMMARY:Ours", "SUMMARY:Ours, changed");
+        memory.edit(uuid, "STATUS:NEEDS-ACTION", "STATUS:COMPLETED");
+
+        let report = sync(&mut todo_list, &memory, &mut state, Policy::Manual).unwrap();
+        assert_eq!((report.pushed, report.pulled), (0, 2));
+        assert_eq!(names(&todo_list), ["Ours, changed", "Theirs"]);
+        assert!(matches!(todo_list.tasks[0].completion, Completion::Complete));
+
+        // What was pulled isn't sent back.
+        let report = sync(&mut todo_list, &memory, &mut state, Policy::Manual).unwrap();
+        assert_eq!((report.pushed, report.pulled), (0, 0));
+    }
+
+    #[test]
+    fn delete_locally() {
+        let (mut todo_list, memory, mut state) = synced(vec![task("Gone"), task("Kept")]);
+        let uuid = todo_list.tasks[0].uuid;
+        todo_list.remove_uuid(uuid);
+        let report = sync(&mut todo_list, &memory, &mut state, Policy::Manual).unwrap();
+        assert_eq!(report.deleted_remotely, 1);
+        assert!(memory.vtodo(uuid).is_none());
+        assert!(!state.tasks.contains_key(&uuid));
+        assert_eq!(memory.resources.borrow().len(), 1);
+    }
+
+    #[test]
+    fn delete_on_the_server() {
+        let mut parent = task("Gone");
+        parent.add_subtask(task("Child"));
+        let (mut todo_list, memory, mut state) = synced(vec![parent]);
+        let href = memory.href(todo_list.tasks[0].uuid).unwrap();
+        memory.resources.borrow_mut().remove(&href);
+        let report = sync(&mut todo_list, &memory, &mut state, Policy::Manual).unwrap();
+        assert_eq!(report.deleted_locally, 1);
+        // The subtask is still on the server, so it is kept as a top-level task.
+        assert_eq!(names(&todo_list), ["Child"]);
+    }
+
+    fn conflicting(policy: Policy) -> (TODOList, Memory, Report) {
+        // Changes a task both here and on the server, then syncs with the given policy.
+        let (mut todo_list, memory, mut state) = synced(vec![task("Task")]);
+        let uuid = todo_list.tasks[0].uuid;
+        todo_list.tasks[0].name = String::from("Ours");
+        memory.edit(uuid, "SUMMARY:Task", "SUMMARY:Theirs");
+        let report = sync(&mut todo_list, &memory, &mut state, policy).unwrap();
+        (todo_list, memory, report)
+    }
+
+    #[test]
+    fn manual_conflicts_are_left_alone() {
+        let (todo_list, memory, report) = conflicting(Policy::Manual);
+        assert_eq!(report.conflicts, ["Ours: it was changed both here and on the server"]);
+        assert_eq!(names(&todo_list), ["Ours"]);
+        assert_eq!(memory.vtodo(todo_list.tasks[0].uuid).unwrap().summary, "Theirs");
+    }
+
+    #[test]
+    fn local_conflicts_keep_ours() {
+        let (todo_list, memory, report) = conflicting(Policy::Local);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.pushed, 1);
+        assert_eq!(memory.vtodo(todo_list.tasks[0].uuid).unwrap().summary, "Ours");
+    }
+
+    #[test]
+    fn remote_conflicts_keep_theirs() {
+        let (todo_list, memory, report) = conflicting(Policy::Remote);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.pulled, 1);
+        assert_eq!(names(&todo_list), ["Theirs"]);
+        assert_eq!(memory.vtodo(todo_list.tasks[0].uuid).unwrap().summary, "Theirs");
+    }
+
+    #[test]
+    fn deletions_which_conflict_with_changes() {
+        for (policy, kept) in [(Policy::Manual, true), (Policy::Local, true), (Policy::Remote, false)] {
+            let (mut todo_list, memory, mut state) = synced(vec![task("Task")]);
+            let uuid = todo_list.tasks[0].uuid;
+            todo_list.tasks[0].name = String::from("Changed");
+            memory.resources.borrow_mut().clear();
+            let report = sync(&mut todo_list, &memory, &mut state, policy).unwrap();
+            assert_eq!(report.conflicts.len(), usize::from(matches!(policy, Policy::Manual)));
+            assert_eq!(!todo_list.tasks.is_empty(), kept);
+            assert_eq!(memory.vtodo(uuid).is_some(), matches!(policy, Policy::Local));
+        }
+        for (policy, kept) in [(Policy::Manual, true), (Policy::Local, false), (Policy::Remote, true)] {
+            let (mut todo_list, memory, mut state) = synced(vec![task("Task")]);
+            let uuid = todo_list.tasks[0].uuid;
+            todo_list.tasks.clear();
+            memory.edit(uuid, "SUMMARY:Task", "SUMMARY:Changed");
+            let report = sync(&mut todo_list, &memory, &mut state, policy).unwrap();
+            assert_eq!(report.conflicts.len(), usize::from(matches!(policy, Policy::Manual)));
+            assert_eq!(memory.vtodo(uuid).is_some(), kept);
+            assert_eq!(!todo_list.tasks.is_empty(), matches!(policy, Policy::Remote));
+        }
+    }
+
+    #[test]
+    fn changes_on_the_server_during_a_sync() {
+        let (mut todo_list, memory, mut state) = synced(vec![task("Task")]);
+        let uuid = todo_list.tasks[0].uuid;
+        todo_list.tasks[0].name = String::from("Changed");
+        memory.meddle.set(true);
+        let report = sync(&mut todo_list, &memory, &mut state, Policy::Manual).unwrap();
+        assert_eq!(report.pushed, 0);
+        assert_eq!(report.conflicts, ["Changed: it was changed on the server during the sync, so it wasn't sent"]);
+        assert_eq!(memory.vtodo(uuid).unwrap().summary, "Task");
+
+        todo_list.tasks.clear();
+        let report = sync(&mut todo_list, &memory, &mut state, Policy::Local).unwrap();
+        assert_eq!(report.deleted_remotely, 0);
+        assert_eq!(report.conflicts.len(), 1);
+        assert!(memory.vtodo(uuid).is_some());
+    }
+
+    #[test]
+    fn connections_lost_during_a_sync() {
+        let (mut todo_list, memory, mut state) = synced(vec![task("Gone"), task("Kept")]);
+        let gone = todo_list.tasks[0].uuid;
+        todo_list.remove_uuid(gone);
+        todo_list.tasks.push(task("First"));
+        todo_list.tasks.push(task("Second"));
+        let (first, second) = (todo_list.tasks[1].uuid, todo_list.tasks[2].uuid);
+        memory.failing.set(Some(2));
+        let report = sync(&mut todo_list, &memory, &mut state, Policy::Manual).unwrap();
+        assert!(report.interrupted.is_some());
+        assert_eq!((report.deleted_remotely, report.pushed), (1, 1));
+        assert!(!state.tasks.contains_key(&gone) && state.tasks.contains_key(&first) && !state.tasks.contains_key(&second));
+
+        // The next sync only sends what wasn't sent, rather than seeing what was as conflicts.
+        memory.failing.set(None);
+        let report = sync(&mut todo_list, &memory, &mut state, Policy::Manual).unwrap();
+        assert!(report.interrupted.is_none() && report.conflicts.is_empty(), "{:?}", report.conflicts);
+        assert_eq!((report.deleted_remotely, report.pushed, report.pulled), (0, 1, 0));
+        assert!(memory.vtodo(second).is_some());
+    }
+
+    #[test]
+    fn multistatus() {
+        let body = r#"<?xml version="1.0"?>
+            <multistatus xmlns="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav" xmlns:x="urn:example">
+              <response><href>/calendars/faros/</href><propstat><prop><getetag>"0"</getetag></prop></propstat></response>
+              <response>
+                <href>/calendars/faros/a.ics</href>
+                <propstat><prop><getetag>"1"</getetag><C:calendar-data>BEGIN:VCALENDAR&#13;
+END:VCALENDAR</C:calendar-data></prop></propstat>
+              </response>
+              <d:response xmlns:d="DAV:"><d:href>b.ics</d:href><d:getetag>"2"</d:getetag><cal:calendar-data xmlns:cal="urn:ietf:params:xml:ns:caldav">data</cal:calendar-data></d:response>
+              <x:response><href>c.ics</href><getetag>"3"</getetag><C:calendar-data>data</C:calendar-data></x:response>
+              <response><x:href>d.ics</x:href><getetag>"4"</getetag><C:calendar-data>data</C:calendar-data></response>
+            </multistatus>"#;
+        let resources = parse_multistatus(body).unwrap();
+        assert_eq!(resources.iter().map(|r| r.href.as_str()).collect::<Vec<_>>(), ["/calendars/faros/a.ics", "b.ics"]);
+        assert_eq!(resources[0].etag, "\"1\"");
+        assert_eq!(resources[0].data, "BEGIN:VCALENDAR\r\nEND:VCALENDAR");
+
+        assert!(parse_multistatus("<multistatus xmlns=\"DAV:\"><response></multistatus>").is_err());
+        assert!(parse_multistatus("Service Unavailable").is_err());
+        assert!(parse_multistatus("<multistatus xmlns=\"DAV:\"/>").unwrap().is_empty());
+    }
+
+    struct Received {
+        method: String,
+        path: String,
+        headers: HashMap<String, String>,
+        body: String
+    }
+
+    #[derive(Default)]
+    struct Served {
+        resources: BTreeMap<String, (String, String)>,
+        etags: usize,
+        received: Vec<Received>
+    }
+
+    impl Served {
+        fn store(&mut self, href: &str, data: &str) -> String {
+            self.etags += 1;
+            let etag = format!("\"{}\"", self.etags);
+            self.resources.insert(String::from(href), (etag.clone(), String::from(data)));
+            etag
+        }
+    }
+
+    fn serve() -> (String, Arc<Mutex<Served>>) {
+        // Runs a stand-in for a CalDAV server over HTTP on a port of its own, which answers REPORT, PUT and DELETE requests from the resources it holds, and records every request it receives. It closes each connection after answering, so that requests can be read without handling keep-alive.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/calendars/faros", listener.local_addr().unwrap());
+        let served = Arc::new(Mutex::new(Served::default()));
+        let shared = Arc::clone(&served);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                answer(stream.unwrap(), &shared);
+            }
+        });
+        (url, served)
+    }
+
+    fn answer(mut stream: TcpStream, served: &Mutex<Served>) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let mut parts = line.split_whitespace().map(String::from);
+        let (method, path) = (parts.next().unwrap(), parts.next().unwrap());
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            match line.trim_end().split_once(':') {
+                Some((name, value)) => headers.insert(name.to_lowercase(), String::from(value.trim())),
+                None => break
+            };
+        }
+        let mut body = vec![0; headers.get("content-length").map_or(0, |length| length.parse().unwrap())];
+        reader.read_exact(&mut body).unwrap();
+        let body = String::from_utf8(body).unwrap();
+
+        let mut served = served.lock().unwrap();
+        let current = served.resources.get(&path).map(|(etag, _)| etag.clone());
+        let matches = match (headers.get("if-match"), headers.get("if-none-match")) {
+            (Some(etag), _) => current.as_ref() == Some(etag),
+            (_, Some(_)) => current.is_none(),
+            _ => true
+        };
+        let (status, etag, response) = match method.as_str() {
+            "REPORT" => {
+                let responses = served.resources.iter().map(|(href, (etag, data))| {
+                    format!("<d:response><d:href>{}</d:href><d:propstat><d:prop><d:getetag>{}</d:getetag><c:calendar-data>{}</c:calendar-data></d:prop></d:propstat></d:response>",
+                            href, etag, data.replace('&', "&amp;").replace('<', "&lt;").replace('\r', "&#13;"))
+                }).collect::<String>();
+                ("207 Multi-Status", None, format!("<d:multistatus xmlns:d=\"DAV:\" xmlns:c=\"urn:ietf:params:xml:ns:caldav\">{}</d:multistatus>", responses))
+            },
+            "PUT" if matches => {
+                let etag = served.store(&path, &body);
+                ("201 Created", Some(etag), String::new())
+            },
+            "DELETE" if current.is_none() => ("404 Not Found", None, String::new()),
+            "DELETE" if matches => {
+                served.resources.remove(&path);
+                ("204 No Content", None, String::new())
+            },
+            _ => ("412 Precondition Failed", None, String::new())
+        };
+        served.received.push(Received { method, path, headers, body });
+        let etag = etag.map_or_else(String::new, |etag| format!("ETag: {}\r\n", etag));
+        write!(stream, "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}", status, response.len(), etag, response).unwrap();
+    }
+
+    #[test]
+    fn http() {
+        let (url, served) = serve();
+        let server = Server::new(&CalDav { url, username: Some(String::from("me")), password: Some(String::from("secret")), conflicts: Policy::Manual }).unwrap();
+        let theirs = list(vec![task("Theirs")]);
+        let theirs_href = server.href(theirs.tasks[0].uuid).unwrap();
+        served.lock().unwrap().store(&theirs_href, &ical::calendar(&[ical::vtodo(&theirs.tasks[0], &theirs)]));
+
+        let mut todo_list = list(vec![task("Ours")]);
+        let ours = todo_list.tasks[0].uuid;
+        let mut state = state();
+        let report = sync(&mut todo_list, &server, &mut state, Policy::Manual).unwrap();
+        assert_eq!((report.pushed, report.pulled), (1, 1));
+        assert_eq!(names(&todo_list), ["Ours", "Theirs"]);
+        {
+            let served = served.lock().unwrap();
+            let (report, put) = (&served.received[0], &served.received[1]);
+            assert_eq!((report.method.as_str(), report.path.as_str()), ("REPORT", "/calendars/faros/"));
+            assert_eq!(report.headers["depth"], "1");
+            assert_eq!(report.headers["authorization"], "Basic bWU6c2VjcmV0");
+            assert!(report.body.contains("calendar-query"));
+            assert_eq!((put.method.as_str(), put.path.as_str()), ("PUT", format!("/calendars/faros/{}.ics", ours).as_str()));
+            assert_eq!(put.headers["if-none-match"], "*");
+            assert!(put.headers["content-type"].starts_with("text/calendar"));
+            assert!(put.body.contains("SUMMARY:Ours"));
+            assert_eq!(state.tasks[&ours].etag, Some(served.resources[&put.path].0.clone()));
+        }
+
+        todo_list.tasks[0].name = String::from("Ours, changed");
+        todo_list.remove_uuid(theirs.tasks[0].uuid);
+        let report = sync(&mut todo_list, &server, &mut state, Policy::Manual).unwrap();
+        assert_eq!((report.pushed, report.deleted_remotely), (1, 1));
+        {
+            let served = served.lock().unwrap();
+            let delete = served.received.iter().find(|request| request.method == "DELETE").unwrap();
+            assert_eq!((delete.path.as_str(), delete.headers["if-match"].as_str()), (theirs_href.as_str(), "\"1\""));
+            let put = served.received.iter().rev().find(|request| request.method == "PUT").unwrap();
+            assert_eq!(put.headers["if-match"], "\"2\"");
+            assert_eq!(served.resources.keys().collect::<Vec<_>>(), [&put.path]);
+        }
+
+        // Requests whose ETags are out of date are refused, and a refusal to store is reported as a conflict rather than an error.
+        let href = server.href(ours).unwrap();
+        assert!(matches!(server.put(&href, "data", Condition::Match("\"stale\"")).unwrap(), Put::Conflict));
+        assert!(matches!(server.put(&href, "data", Condition::Create).unwrap(), Put::Conflict));
+        assert!(!server.delete(&href, "\"stale\"").unwrap());
+        assert!(server.delete(&theirs_href, "\"1\"").unwrap());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{Completion, TODOList};
+
+pub enum Kind {
+    Task { incomplete_only: bool },
+    Tag,
+    List
+}
+
+// Each shell's completion script generated by clap completes commands and options, and is followed by one of these, which asks faros for live task, tag and list names wherever they are expected, and otherwise falls back to clap's completions.
+const BASH: &str = r#"
+_faros_live() {
+    local candidates candidate
+    mapfile -t candidates < <(faros __complete "$COMP_CWORD" "${COMP_WORDS[@]}" 2>/dev/null)
+    if [[ ${#candidates[@]} -gt 0 ]]; then
+        COMPREPLY=()
+        for candidate in "${candidates[@]}"; do
+            COMPREPLY+=("$(printf '%q' "$candidate")")
+        done
+        return 0
+    fi
+    _faros "$@"
+}
+
+complete -F _faros_live -o bashdefault -o default faros
+"#;
+
+const ZSH: &str = r#"
+_faros_live() {
+    local -a candidates
+    candidates=("${(@f)$(faros __complete $((CURRENT - 1)) "${words[@]}" 2>/dev/null)}")
+    if [[ -n "${candidates[1]}" ]]; then
+        compadd -U -- "${candidates[@]}"
+        return
+    fi
+    _faros "$@"
+}
+
+if [ "$funcstack[1]" = "_faros" ]; then
+    _faros_live "$@"
+else
+    compdef _faros_live faros
+fi
+"#;
+
+const FISH: &str = r#"
+function __faros_live
+    set -l words (commandline -opc)
+    faros __complete (count $words) $words (commandline -ct) 2>/dev/null
+end
+
+complete -c faros -n '__faros_live | string length -q' -f -a '(__faros_live)'
+"#;
+
+pub fn script(shell: &str, generated: &str) -> String {
+    // Adds live completions to the script clap generated. zsh scripts end by calling the completion function, which is replaced by a call to the live one.
+    match shell {
+        "bash" => format!("{}{}", generated, BASH),
+        "zsh" => format!("{}{}", generated.trim_end().trim_end_matches("_faros \"$@\""), ZSH),
+        _ => format!("{}{}", generated, FISH)
+    }
+}
+
+pub fn candidates(todo_list: &TODOList, kind: &Kind, lists: Vec<String>, prefix: &str) -> Vec<String> {
+    // Lists everything which could be given where the word being completed is, beginning with what has been typed so far. Tasks can be given by name, short ID or path, so all three are offered.
+    let mut candidates = match kind {
+        Kind::Task { incomplete_only } => {
+            let mut candidates = Vec::new();
+            for task in todo_list.flattened() {
+                if *incomplete_only && matches!(task.completion, Completion::Complete) {
+                    continue;
+                }
+                candidates.push(task.name.clone());
+                candidates.push(task.short_id());
+                let path = todo_list.path(task.uuid);
+                if path.len() > 1 {
+                    candidates.push(path.iter().map(|t| t.name.as_str()).collect::<Vec<_>>().join("/"));
+                }
+            }
+            candidates
+        },
+        Kind::Tag => todo_list.tags.iter().map(|tag| tag.name.clone()).collect(),
+        Kind::List => lists
+    };
+    candidates.retain(|candidate| candidate.starts_with(prefix));
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+pub fn unquote(word: &str) -> String {
+    // The word being completed is given as it was typed, so quotes and backslashes are removed to find what it means. It may still be missing its closing quote.
+    crate::alias::split(word)
+        .or_else(|_| crate::alias::split(&format!("{}\"", word)))
+        .or_else(|_| crate::alias::split(&format!("{}'", word)))
+        .map(|words| words.join(" "))
+        .unwrap_or_else(|_| String::from(word))
+}
@@ -0,0 +1,420 @@
+use chrono::NaiveTime;
+use chrono::format::{Item, StrftimeItems};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use std::{fmt, fs, path, str};
+use crate::Priority;
+use crate::filter::Filter;
+use crate::output::Format;
+use crate::render::Column;
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub default_view: Option<String>,
+    pub defaults: Defaults,
+    pub colors: Colors,
+    pub views: BTreeMap<String, View>,
+    pub aliases: BTreeMap<String, String>,
+    pub caldav: Option<CalDav>,
+    pub storage: Storage
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Defaults {
+    #[serde(with = "time")]
+    pub due_time: NaiveTime,
+    #[serde(with = "text")]
+    pub priority: Priority,
+    pub days: u32,
+    #[serde(deserialize_with = "date_format")]
+    pub date_format: String,
+    pub sort: Sort,
+    pub prompt: bool,
+    pub confirm_above: usize
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    pub mode: ColorMode,
+    #[serde(with = "text")]
+    pub high: Style,
+    #[serde(with = "text")]
+    pub medium: Style,
+    #[serde(with = "text")]
+    pub low: Style,
+    #[serde(with = "text")]
+    pub overdue: Style,
+    #[serde(with = "text")]
+    pub header: Style,
+    #[serde(with = "text")]
+    pub highlight: Style
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    #[default]
+    Auto,
+    Always,
+    Never
+}
+
+// A text style given as words such as "bold red", along with the escape code which produces it.
+#[derive(Clone)]
+pub struct Style {
+    name: String,
+    code: String
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct View {
+    pub filter: String,
+    pub sort: Option<Sort>,
+    pub columns: Vec<Column>,
+    pub limit: Option<usize>,
+    pub format: Format
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    Due,
+    Priority,
+    Name
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Storage {
+    pub backend: Backend,
+    pub remote: Option<String>,
+    pub branch: String
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    File,
+    Git
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CalDav {
+    pub url: String,
+    pub username: Option<String>,
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub conflicts: Policy
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Policy {
+    Local,
+    Remote,
+    #[default]
+    Manual
+}
+
+impl str::FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Policy, String> {
+        match s.to_lowercase().as_str() {
+            "local" => Ok(Policy::Local),
+            "remote" => Ok(Policy::Remote),
+            "manual" => Ok(Policy::Manual),
+            _ => Err(format!("Unknown conflict policy \"{}\", expected local, remote or manual.", s))
+        }
+    }
+}
+
+impl Default for Storage {
+    fn default() -> Storage {
+        Storage {
+            backend: Backend::File,
+            remote: None,
+            branch: String::from("main")
+        }
+    }
+}
+
+impl Default for View {
+    fn default() -> View {
+        View {
+            filter: String::new(),
+            sort: None,
+            columns: vec![Column::Id, Column::Status, Column::Name, Column::Due, Column::Priority],
+            limit: None,
+            format: Format::Table
+        }
+    }
+}
+
+impl Default for Defaults {
+    fn default() -> Defaults {
+        Defaults {
+            due_time: NaiveTime::from_hms(23, 59, 0),
+            priority: Priority::Medium,
+            days: 3,
+            date_format: String::from("%Y-%m-%d %H:%M"),
+            sort: Sort::Due,
+            prompt: true,
+            confirm_above: 10
+        }
+    }
+}
+
+impl Default for Colors {
+    fn default() -> Colors {
+        let style = |name: &str| name.parse().unwrap();
+        Colors {
+            mode: ColorMode::Auto,
+            high: style("red"),
+            medium: style("yellow"),
+            low: style("blue"),
+            overdue: style("bold red"),
+            header: style("bold"),
+            highlight: style("bold underline")
+        }
+    }
+}
+
+impl str::FromStr for Style {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Style, String> {
+        // Styles are made of any number of attributes and at most one color each for the text and, with "on", the background, such as "bold white on red". "none" is no style at all.
+        let mut codes = Vec::new();
+        let mut background = false;
+        for word in s.split(|c: char| c.is_whitespace() || c == '+').filter(|w| !w.is_empty()) {
+            let word = word.to_lowercase();
+            let attribute = match word.as_str() {
+                "none" => continue,
+                "on" => {
+                    background = true;
+                    continue;
+                },
+                "bold" => Some(1),
+                "dim" => Some(2),
+                "italic" => Some(3),
+                "underline" => Some(4),
+                "reverse" => Some(7),
+                _ => None
+            };
+            let code = match attribute {
+                Some(code) => code,
+                None => {
+                    let (bright, name) = match word.strip_prefix("bright-") {
+                        Some(name) => (true, name),
+                        None => (false, word.as_str())
+                    };
+                    let color = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"].iter().position(|c| *c == name).ok_or_else(|| {
+                        format!("Unknown style \"{}\" in \"{}\", expected none, bold, dim, italic, underline, reverse, or a color such as red or bright-blue, optionally after \"on\" for the background", word, s)
+                    })?;
+                    let base = match (background, bright) {
+                        (false, false) => 30,
+                        (true, false) => 40,
+                        (false, true) => 90,
+                        (true, true) => 100
+                    };
+                    background = false;
+                    base + color
+                }
+            };
+            codes.push(code.to_string());
+        }
+        let code = if codes.is_empty() { String::new() } else { format!("\x1b[{}m", codes.join(";")) };
+        Ok(Style { name: String::from(s), code })
+    }
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl Style {
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+}
+
+mod text {
+    // Reads and writes settings as text, using the FromStr and Display implementations of their types, such as priorities and styles.
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::{fmt, str};
+
+    pub fn serialize<T: fmt::Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T: str::FromStr<Err = String>, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+mod time {
+    use chrono::NaiveTime;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&time.format("%H:%M"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        NaiveTime::parse_from_str(&text, "%H:%M").map_err(|_| de::Error::custom(format!("\"{}\" is not a time of day, expected HH:MM, such as 17:30", text)))
+    }
+}
+
+fn date_format<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    // Formats are checked when they are read, as chrono panics when asked to format a date with an invalid format.
+    let format = String::deserialize(deserializer)?;
+    if StrftimeItems::new(&format).any(|item| item == Item::Error) {
+        return Err(serde::de::Error::custom(format!("\"{}\" is not a valid date format, such as %Y-%m-%d %H:%M", format)));
+    }
+    Ok(format)
+}
+
+static DEFAULTS: OnceLock<Defaults> = OnceLock::new();
+static COLORS: OnceLock<Colors> = OnceLock::new();
+
+pub fn defaults() -> &'static Defaults {
+    // The defaults in the configuration file, which are needed deep within faros, such as when an importer gives a task a due date. Until the configuration is read, and in commands which never read it, such as faros merge, the built-in defaults are used.
+    DEFAULTS.get_or_init(Defaults::default)
+}
+
+pub fn colors() -> &'static Colors {
+    COLORS.get_or_init(Colors::default)
+}
+
+pub fn aliases() -> BTreeMap<String, String> {
+    // Reads only the aliases from the configuration file, as they are needed before the command line is parsed, which is before the rest of the configuration is read and checked. A configuration file which can't be read has no aliases, and its problems are reported once it is read in full.
+    path().and_then(|path| fs::read_to_string(path).ok())
+          .and_then(|contents| toml::from_str::<toml::Value>(&contents).ok())
+          .and_then(|config| config.get("aliases").and_then(|aliases| aliases.as_table()).cloned())
+          .map(|aliases| aliases.into_iter().filter_map(|(name, expansion)| expansion.as_str().map(|e| (name, String::from(e)))).collect())
+          .unwrap_or_default()
+}
+
+pub fn path() -> Option<path::PathBuf> {
+    crate::config_dir().map(|dir| dir.join("config.toml"))
+}
+
+impl Config {
+    pub fn parse(contents: &str) -> Result<Config, String> {
+        // Parses and checks a configuration, describing the first problem found, along with where it is where possible.
+        let config: Config = toml::from_str(contents).map_err(|err| err.to_string())?;
+        for (name, view) in &config.views {
+            Filter::parse(&view.filter).map_err(|err| format!("The view named {} has an invalid filter: {}", name, err))?;
+        }
+        if let Some(name) = &config.default_view {
+            if !config.views.contains_key(name) {
+                return Err(format!("The default view {} is not defined.", name));
+            }
+        }
+        for (name, expansion) in &config.aliases {
+            if name.is_empty() || name.starts_with('-') || name.chars().any(char::is_whitespace) {
+                return Err(format!("\"{}\" can't be used as the name of an alias, as it isn't a single word.", name));
+            }
+            if crate::is_command(name) {
+                return Err(format!("The alias {} has the same name as one of faros's commands, which would always be used instead.", name));
+            }
+            crate::alias::split(expansion).map_err(|err| format!("The alias {} is invalid: {}", name, err))?;
+        }
+        Ok(config)
+    }
+
+    pub fn read() -> Result<Config, String> {
+        // Reads the configuration from config.toml in the configuration directory, usually ~/.config/faros. A missing file is equivalent to an empty one.
+        let path = match path() {
+            Some(path) => path,
+            None => return Ok(Config::default())
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) if !path.exists() => return Ok(Config::default()),
+            Err(_) => return Err(format!("{} exists, but couldn't be read.", path.display()))
+        };
+        let config = Config::parse(&contents).map_err(|err| format!("{} is invalid: {}", path.display(), err))?;
+        let _ = DEFAULTS.set(config.defaults.clone());
+        let _ = COLORS.set(config.colors.clone());
+        Ok(config)
+    }
+
+    pub fn view(&self, name: &str) -> Option<View> {
+        self.views.get(name).cloned()
+    }
+
+    pub fn default_view(&self) -> View {
+        // The view used by a bare `faros list`. Without a default view, it lists every task due within the number of days in the defaults.
+        self.default_view.as_ref()
+            .and_then(|name| self.view(name))
+            .unwrap_or_else(|| View {
+                filter: format!("due:{}", self.defaults.days),
+                ..View::default()
+            })
+    }
+
+    pub fn settings(&self) -> Vec<(String, toml::Value)> {
+        // Lists every setting in effect, including defaults which aren't in the configuration file, as dotted keys such as defaults.priority.
+        fn flatten(prefix: &str, value: toml::Value, settings: &mut Vec<(String, toml::Value)>) {
+            match value {
+                toml::Value::Table(table) => {
+                    for (key, value) in table {
+                        let key = if prefix.is_empty() { key } else { format!("{}.{}", prefix, key) };
+                        flatten(&key, value, settings);
+                    }
+                },
+                value => settings.push((String::from(prefix), value))
+            }
+        }
+        let mut settings = Vec::new();
+        if let Ok(value) = toml::Value::try_from(self) {
+            flatten("", value, &mut settings);
+        }
+        settings
+    }
+}
+
+pub fn set(contents: &str, key: &str, value: &str) -> Result<String, String> {
+    // Changes a setting in the text of a configuration file, keeping the rest of the file as it was, comments and all, and returns the new text once it has been checked. The value is written as a string if the setting is one, and otherwise as a TOML value, such as a number, a boolean or an array.
+    let mut document = contents.parse::<toml_edit::DocumentMut>().map_err(|err| err.to_string())?;
+    let keys = key.split('.').collect::<Vec<_>>();
+    if keys.iter().any(|k| k.is_empty()) {
+        return Err(format!("\"{}\" is not the name of a setting, such as defaults.priority.", key));
+    }
+    let current = Config::parse(contents).unwrap_or_default().settings().into_iter().find(|(k, _)| k == key).map(|(_, v)| v);
+    let item = match current {
+        Some(toml::Value::String(_)) => toml_edit::Value::from(value),
+        _ => value.parse::<toml_edit::Value>().unwrap_or_else(|_| toml_edit::Value::from(value))
+    };
+
+    let (name, sections) = keys.split_last().unwrap();
+    let mut table: &mut dyn toml_edit::TableLike = document.as_table_mut();
+    for section in sections {
+        if table.get(section).is_none() {
+            let mut new = toml_edit::Table::new();
+            new.set_implicit(true);
+            table.insert(section, toml_edit::Item::Table(new));
+        }
+        table = table.get_mut(section)
+                     .and_then(|item| item.as_table_like_mut())
+                     .ok_or_else(|| format!("{} is a setting, not a section of the configuration file.", section))?;
+    }
+    table.insert(name, toml_edit::Item::Value(item));
+
+    let contents = document.to_string();
+    Config::parse(&contents)?;
+    Ok(contents)
+}
@@ -0,0 +1,262 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use std::collections::HashMap;
+use uuid::Uuid;
+use crate::{Completion, Priority, TODOList, Task};
+
+const FIELDS: [&str; 6] = ["name", "description", "due", "priority", "tags", "parent"];
+
+pub struct Options<'a> {
+    pub mappings: Vec<&'a str>,
+    pub delimiter: u8,
+    pub date_format: Option<&'a str>,
+    pub under: Option<Uuid>
+}
+
+enum Parent {
+    Row(usize),
+    Task(Uuid)
+}
+
+fn parse_due(text: &str, date_format: Option<&str>) -> Option<DateTime<Local>> {
+    // Dates are read with the given format if there is one, and otherwise as ISO 8601 dates, with or without a time.
+    let local = |d: NaiveDateTime| Local.from_local_datetime(&d).earliest();
+    match date_format {
+        Some(format) => NaiveDateTime::parse_from_str(text, format).ok().and_then(local)
+                            .or_else(|| NaiveDate::parse_from_str(text, format).ok().map(crate::due_on)),
+        None => DateTime::parse_from_rfc3339(text).ok().map(|d| d.with_timezone(&Local))
+                    .or_else(|| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").ok().and_then(local))
+                    .or_else(|| NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M").ok().and_then(local))
+                    .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok().map(crate::due_on))
+    }
+}
+
+fn columns(headers: &csv::StringRecord, mappings: &[&str]) -> Result<HashMap<&'static str, usize>, Vec<String>> {
+    // Maps each field onto the column with the same name, unless a mapping such as "name=Title" says otherwise. Columns can also be given by number, counting from 1.
+    let mut columns = HashMap::new();
+    let mut errors = Vec::new();
+    for field in FIELDS.iter() {
+        if let Some(i) = headers.iter().position(|header| header.trim().eq_ignore_ascii_case(field)) {
+            columns.insert(*field, i);
+        }
+    }
+    for mapping in mappings {
+        let (field, column) = match mapping.split_once('=') {
+            Some(split) => split,
+            None => {
+                errors.push(format!("\"{}\" is not a mapping, expected [field]=[column].", mapping));
+                continue;
+            }
+        };
+        let field = match FIELDS.iter().find(|f| f.eq_ignore_ascii_case(field.trim())) {
+            Some(field) => *field,
+            None => {
+                errors.push(format!("\"{}\" is not a field, expected one of {}.", field, FIELDS.join(", ")));
+                continue;
+            }
+        };
+        match headers.iter().position(|header| header.trim() == column.trim()) {
+            Some(i) => {
+                columns.insert(field, i);
+            },
+            None => match column.trim().parse::<usize>() {
+                Ok(n) if n >= 1 && n <= headers.len() => {
+                    columns.insert(field, n - 1);
+                },
+                _ => errors.push(format!("There is no column named \"{}\".", column))
+            }
+        }
+    }
+    if !columns.contains_key("name") {
+        errors.push(String::from("No column is mapped to the name field. Use --map name=[column] to choose one."));
+    }
+    if errors.is_empty() {
+        Ok(columns)
+    } else {
+        Err(errors)
+    }
+}
+
+pub fn import(contents: &str, todo_list: &mut TODOList, options: &Options) -> Result<Vec<Uuid>, Vec<String>> {
+    // Adds a task for each row of a CSV file, returning the UUIDs of the tasks added at the top of the imported tree. Every row is validated before anything is added, and if any row is invalid, nothing is added and an error is returned for each invalid row. Parents may be earlier rows, named by their name, or tasks already in the TODO list, named by their name, ID or path.
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .flexible(true)
+        .from_reader(contents.as_bytes());
+    let headers = reader.headers().map_err(|err| vec![format!("The header row couldn't be read: {}", err)])?.clone();
+    let columns = columns(&headers, &options.mappings)?;
+
+    let mut errors = Vec::new();
+    let mut rows: Vec<(Task, Option<Parent>, Vec<String>)> = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let row = i + 2;
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                errors.push(format!("row {}: {}", row, err));
+                continue;
+            }
+        };
+        let field = |name: &str| columns.get(name).and_then(|&i| record.get(i)).map(str::trim).filter(|value| !value.is_empty());
+
+        let name = match field("name") {
+            Some(name) => String::from(name),
+            None => {
+                errors.push(format!("row {}: the task has no name", row));
+                continue;
+            }
+        };
+        let due_date = match field("due").map(|due| (due, parse_due(due, options.date_format))) {
+            Some((_, Some(due_date))) => due_date,
+            Some((due, None)) => {
+                errors.push(format!("row {}: \"{}\" is not a valid date", row, due));
+                continue;
+            },
+            None => crate::default_due_date()
+        };
+        let priority = match field("priority").map(str::parse::<Priority>) {
+            Some(Ok(priority)) => priority,
+            Some(Err(err)) => {
+                errors.push(format!("row {}: {}", row, err));
+                continue;
+            },
+            None => crate::config::defaults().priority
+        };
+        let parent = match field("parent") {
+            Some(parent) => match rows.iter().rposition(|(task, _, _)| task.name == parent) {
+                Some(index) => Some(Parent::Row(index)),
+                // Rows are checked without asking anything, so that a dry run never stops halfway through to ask which task was meant.
+                None => match todo_list.tasks_named(parent).as_slice() {
+                    [task] => Some(Parent::Task(task.uuid)),
+                    [] => {
+                        errors.push(format!("row {}: there is no task named {}, either in an earlier row or in your TODO list", row, parent));
+                        continue;
+                    },
+                    tasks => {
+                        let ids = tasks.iter().map(|task| task.short_id()).collect::<Vec<_>>().join(", ");
+                        errors.push(format!("row {}: more than one task in your TODO list is named {} ({}), so name it by its ID or path instead", row, parent, ids));
+                        continue;
+                    }
+                }
+            },
+            None => None
+        };
+        let tags = field("tags").map_or_else(Vec::new, |tags| {
+            tags.split([',', ';']).map(str::trim).filter(|tag| !tag.is_empty()).map(String::from).collect()
+        });
+        let description = String::from(field("description").unwrap_or(""));
+        rows.push((Task::new(name, description, priority, due_date), parent, tags));
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // Rows are taken from the last to the first, so that every row's subtasks are in place before it is added to its own parent.
+    let mut children: Vec<Vec<Task>> = rows.iter().map(|_| Vec::new()).collect();
+    let mut roots = Vec::new();
+    while let Some((mut task, parent, tags)) = rows.pop() {
+        let mut subtasks = children.pop().unwrap();
+        subtasks.reverse();
+        task.completion = Completion::Incomplete(subtasks);
+        task.tags = tags.iter().map(|tag| todo_list.tag_uuid(tag)).collect();
+        match parent {
+            Some(Parent::Row(index)) => children[index].push(task),
+            Some(Parent::Task(uuid)) => roots.push((task, Some(uuid))),
+            None => roots.push((task, options.under))
+        }
+    }
+    roots.reverse();
+    let uuids = roots.iter().map(|(task, _)| task.uuid).collect();
+    for (task, parent) in roots {
+        match parent {
+            Some(parent) => todo_list.task_from_uuid(parent).add_subtask(task),
+            None => todo_list.tasks.push(task)
+        }
+    }
+    Ok(uuids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options<'a>() -> Options<'a> {
+        Options { mappings: Vec::new(), delimiter: b',', date_format: None, under: None }
+    }
+
+    fn list_with(names: &[&str]) -> TODOList {
+        let mut todo_list = TODOList { tasks: Vec::new(), tags: Vec::new() };
+        for name in names {
+            todo_list.tasks.push(Task::new(String::from(*name), String::new(), Priority::Medium, crate::default_due_date()));
+        }
+        todo_list
+    }
+
+    #[test]
+    fn every_field_is_read() {
+        let mut todo_list = list_with(&["Inbox"]);
+        let contents = "name,description,due,priority,tags,parent\n\
+                        Report,\"Write it, then send it\",2030-01-02 09:30,high,\"work, urgent\",Inbox\n\
+                        Draft,,2030-01-01,low,,Report\n";
+        let roots = import(contents, &mut todo_list, &options()).unwrap();
+        assert_eq!(roots.len(), 1);
+        let report = &todo_list.tasks[0].children()[0];
+        assert_eq!(report.uuid, roots[0]);
+        assert_eq!(report.name, "Report");
+        assert_eq!(report.description, "Write it, then send it");
+        assert_eq!(report.due_date.format("%Y-%m-%d %H:%M").to_string(), "2030-01-02 09:30");
+        assert!(report.priority == Priority::High);
+        assert_eq!(crate::render::tag_names(report, &todo_list.tags), ["work", "urgent"]);
+        let draft = &report.children()[0];
+        assert_eq!(draft.name, "Draft");
+        assert!(draft.priority == Priority::Low);
+        assert_eq!(draft.due_date.naive_local().date(), NaiveDate::from_ymd(2030, 1, 1));
+    }
+
+    #[test]
+    fn mappings_and_formats() {
+        let mut todo_list = list_with(&[]);
+        let options = Options { mappings: vec!["name=Title", "due=3"], delimiter: b';', date_format: Some("%d/%m/%Y"), under: None };
+        import("Title;Notes;When\nPost;Letters;05/06/2030\n", &mut todo_list, &options).unwrap();
+        assert_eq!(todo_list.tasks[0].name, "Post");
+        assert!(todo_list.tasks[0].description.is_empty());
+        assert_eq!(todo_list.tasks[0].due_date.naive_local().date(), NaiveDate::from_ymd(2030, 6, 5));
+    }
+
+    #[test]
+    fn invalid_rows_add_nothing() {
+        let mut todo_list = list_with(&["Inbox"]);
+        let contents = "name,due,priority,parent\n\
+                        Fine,2030-01-01,high,\n\
+                        ,2030-01-01,high,\n\
+                        Late,tomorrow,high,\n\
+                        Urgent,2030-01-01,very,\n\
+                        Orphan,2030-01-01,high,Nowhere\n";
+        let errors = import(contents, &mut todo_list, &options()).err().unwrap();
+        assert_eq!(errors.len(), 4, "{:?}", errors);
+        for (error, row) in errors.iter().zip(3..) {
+            assert!(error.starts_with(&format!("row {}:", row)), "{}", error);
+        }
+        assert_eq!(todo_list.flattened().len(), 1);
+    }
+
+    #[test]
+    fn malformed_headers() {
+        let mut todo_list = list_with(&[]);
+        assert_eq!(import("title,due\nPost,\n", &mut todo_list, &options()).err().unwrap().len(), 1);
+        let options = Options { mappings: vec!["name", "colour=2", "name=Missing"], ..options() };
+        assert_eq!(import("title,due\nPost,\n", &mut todo_list, &options).err().unwrap().len(), 4);
+        assert!(todo_list.tasks.is_empty());
+    }
+
+    #[test]
+    fn parents_are_found_without_asking() {
+        let mut todo_list = list_with(&["Inbox", "Inbox"]);
+        let errors = import("name,parent\nPost,Inbox\n", &mut todo_list, &options()).err().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("more than one task"), "{}", errors[0]);
+
+        let id = todo_list.tasks[1].short_id();
+        import(&format!("name,parent\nPost,{}\n", id), &mut todo_list, &options()).unwrap();
+        assert_eq!(todo_list.tasks[1].children()[0].name, "Post");
+    }
+}
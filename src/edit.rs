@@ -0,0 +1,269 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use std::{env, fs, path};
+use std::process::Command;
+use uuid::Uuid;
+use crate::{Completion, Priority, TODOList, Task};
+use crate::outline;
+
+const TREE_HEADER: &str = "# Reorder, indent, rename, add and remove tasks below, then save and quit. Each task is followed by its ID, so keep the ID with the task; lines without one become new tasks, and tasks whose lines are removed are removed along with their subtasks. Lines beginning with # are ignored, and emptying the file leaves your tasks as they were.\n";
+const HEADER: &str = "# Edit the task below, then save and quit. Lines beginning with # above the description are ignored, and emptying the file leaves the task as it was.\n";
+
+pub struct Fields {
+    pub name: String,
+    pub due_date: Option<DateTime<Local>>,
+    pub priority: Option<Priority>,
+    pub tags: Option<Vec<String>>,
+    pub description: String
+}
+
+pub fn render(task: &Task, todo_list: &TODOList) -> String {
+    // Writes a task as front matter holding its fields, followed by its description, which can be as long as it needs to be.
+    format!("{}---\nname: {}\ndue: {}\npriority: {}\ntags: {}\n---\n{}\n",
+            HEADER,
+            task.name,
+            task.due_date.format("%Y-%m-%d %H:%M"),
+            task.priority,
+            crate::render::tag_names(task, &todo_list.tags).join(", "),
+            task.description)
+}
+
+fn parse_due(text: &str) -> Result<DateTime<Local>, String> {
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M")
+        .ok()
+        .and_then(|date| Local.from_local_datetime(&date).single())
+        .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok().map(crate::due_on))
+        .ok_or_else(|| format!("The due date \"{}\" isn't a date, expected YYYY-MM-DD or YYYY-MM-DD HH:MM.", text))
+}
+
+pub fn parse(text: &str) -> Result<Fields, String> {
+    // Reads a task written by render back in. Fields which have been removed are left as they were, except for the name, which is required.
+    let mut lines = text.lines().skip_while(|line| line.trim().is_empty() || line.starts_with('#'));
+    if lines.next().map(str::trim) != Some("---") {
+        return Err(String::from("The task's fields should begin with a line holding only ---."));
+    }
+    let mut fields = Fields { name: String::new(), due_date: None, priority: None, tags: None, description: String::new() };
+    let mut seen: Vec<String> = Vec::new();
+    let mut closed = false;
+    for line in lines.by_ref() {
+        if line.trim() == "---" {
+            closed = true;
+            break;
+        }
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once(':').ok_or_else(|| format!("\"{}\" isn't a field, such as \"name: Write report\".", line))?;
+        let (key, value) = (key.trim().to_lowercase(), value.trim());
+        if seen.contains(&key) {
+            return Err(format!("The {} field is given more than once.", key));
+        }
+        match key.as_str() {
+            "name" => fields.name = String::from(value),
+            "due" => fields.due_date = Some(parse_due(value)?),
+            "priority" => fields.priority = Some(value.parse()?),
+            "tags" => fields.tags = Some(value.split(',').map(str::trim).filter(|tag| !tag.is_empty()).map(String::from).collect()),
+            _ => return Err(format!("There is no field named {}, expected name, due, priority or tags.", key))
+        }
+        seen.push(key);
+    }
+    if !closed {
+        return Err(String::from("The task's fields should end with a line holding only ---, followed by its description."));
+    }
+    if fields.name.is_empty() {
+        return Err(String::from("The task needs a name."));
+    }
+    fields.description = String::from(lines.collect::<Vec<_>>().join("\n").trim());
+    Ok(fields)
+}
+
+fn run_editor(file: &path::Path) -> Result<(), String> {
+    // Runs $VISUAL or $EDITOR, or vi if neither is set, through the shell, so that editors given with arguments, such as "code --wait", work.
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| String::from("vi"));
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(file)
+        .status()
+        .map_err(|_| format!("Your editor, {}, could not be run.", editor))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("Your editor, {}, exited with an error, so nothing was changed.", editor))
+    }
+}
+
+fn edit_text<T>(name: &str, text: String, parse: &dyn Fn(&str) -> Result<T, String>) -> Result<Option<T>, String> {
+    // Opens some text in the user's editor until it is saved in a form which can be read, reopening it with the problem noted at the top otherwise. Returns None if the file was emptied, or if it was saved without fixing the problem.
+    let file = env::temp_dir().join(format!("faros-{}-{}.md", name, std::process::id()));
+    let mut text = text;
+    let result = loop {
+        if let Err(err) = fs::write(&file, &text).map_err(|_| format!("{} could not be written to.", file.display())).and_then(|_| run_editor(&file)) {
+            break Err(err);
+        }
+        let edited = match fs::read_to_string(&file) {
+            Ok(edited) => edited,
+            Err(_) => break Err(format!("{} could not be read.", file.display()))
+        };
+        if edited.lines().all(|line| line.trim().is_empty() || line.starts_with('#')) {
+            break Ok(None);
+        }
+        match parse(&edited) {
+            Ok(parsed) => break Ok(Some(parsed)),
+            Err(_) if edited == text => {
+                break Err(String::from("The file was saved without fixing the problem with it, so nothing was changed."));
+            },
+            Err(err) => {
+                let rest = edited.lines().skip_while(|line| line.starts_with("# Error: ")).collect::<Vec<_>>().join("\n");
+                text = format!("# Error: {}\n{}\n", err, rest);
+            }
+        }
+    };
+    let _ = fs::remove_file(&file);
+    result
+}
+
+pub fn edit(task: &Task, todo_list: &TODOList) -> Result<Option<Fields>, String> {
+    edit_text(&task.uuid.to_string(), render(task, todo_list), &parse)
+}
+
+pub fn apply(fields: Fields, uuid: Uuid, todo_list: &mut TODOList) {
+    // Updates a task with the fields read back from the editor. Tags which don't exist yet are created.
+    let tags = fields.tags.map(|tags| tags.iter().map(|tag| todo_list.tag_uuid(tag)).collect());
+    let task = todo_list.task_from_uuid(uuid);
+    task.name = fields.name;
+    task.description = fields.description;
+    if let Some(due_date) = fields.due_date {
+        task.due_date = due_date;
+    }
+    if let Some(priority) = fields.priority {
+        task.priority = priority;
+    }
+    if let Some(tags) = tags {
+        task.tags = tags;
+    }
+}
+
+pub struct TreeChanges {
+    pub todo_list: TODOList,
+    pub kept: usize,
+    pub added: usize,
+    pub removed: usize,
+    pub problems: Vec<String>
+}
+
+fn apply_tree(todo_list: &TODOList, root: Option<Uuid>, text: &str) -> Result<TreeChanges, String> {
+    // Rebuilds a subtree, or the whole TODO list, from an outline. Lines which kept the ID of a task in the subtree update that task, keeping its UUID and everything the outline doesn't show, such as its description. Other lines become new tasks, and tasks whose lines are gone are removed. The changes are made to a copy of the TODO list, so that nothing is changed if the outline can't be read.
+    let mut todo_list = todo_list.clone();
+    let roots = match root {
+        Some(uuid) => todo_list.path(uuid).pop().into_iter().collect::<Vec<_>>(),
+        None => todo_list.tasks.iter().collect()
+    };
+    let originals = roots.iter().flat_map(|root| root.flattened()).cloned().collect::<Vec<_>>();
+
+    // Comments are blanked out rather than removed, so that the line numbers of problems are those of the file.
+    let outline = text.lines().map(|line| if line.starts_with('#') { "" } else { line }).collect::<Vec<_>>().join("\n");
+    let (mut nodes, problems) = outline::parse_markdown(&outline, &mut todo_list);
+    if !problems.is_empty() {
+        return Err(problems.join("; "));
+    }
+    let mut used = Vec::new();
+    for node in &mut nodes {
+        let original = node.id
+                           .as_ref()
+                           .filter(|id| id.len() >= 8)
+                           .and_then(|id| originals.iter().find(|task| task.uuid.to_simple().to_string().starts_with(id.as_str())))
+                           .filter(|task| !used.contains(&task.uuid));
+        if let Some(original) = original {
+            // A line whose due date was removed keeps the task's due date, rather than being given the default one.
+            let mut task = original.clone();
+            task.name = node.task.name.clone();
+            if node.due_given {
+                task.due_date = node.task.due_date;
+            }
+            task.tags = node.task.tags.clone();
+            used.push(task.uuid);
+            node.task = task;
+        }
+    }
+    let mut problems = Vec::new();
+    let mut tasks = outline::build(nodes, &mut problems);
+    // Subtasks of checked-off tasks are left out when the tree is built, so the tasks are counted once it has been.
+    let built = tasks.iter().flat_map(|task| task.flattened()).map(|task| task.uuid).collect::<Vec<_>>();
+    let kept = used.iter().filter(|uuid| built.contains(uuid)).count();
+
+    match root.and_then(|uuid| todo_list.path(uuid).iter().rev().nth(1).map(|parent| parent.uuid)) {
+        Some(parent) => {
+            let root = root.unwrap();
+            if let Completion::Incomplete(children) = &mut todo_list.task_from_uuid(parent).completion {
+                let position = children.iter().position(|child| child.uuid == root).unwrap();
+                children.splice(position..=position, tasks);
+            }
+        },
+        None => match root {
+            Some(root) => {
+                let position = todo_list.tasks.iter().position(|task| task.uuid == root).unwrap();
+                todo_list.tasks.splice(position..=position, tasks);
+            },
+            None => todo_list.tasks = std::mem::take(&mut tasks)
+        }
+    }
+    Ok(TreeChanges { todo_list, kept, added: built.len() - kept, removed: originals.len() - kept, problems })
+}
+
+pub fn edit_tree(todo_list: &TODOList, root: Option<Uuid>) -> Result<Option<TreeChanges>, String> {
+    let roots = match root {
+        Some(uuid) => todo_list.path(uuid).pop().into_iter().collect::<Vec<_>>(),
+        None => todo_list.tasks.iter().collect()
+    };
+    let text = format!("{}{}", TREE_HEADER, outline::export_outline(&roots, todo_list));
+    edit_text("tree", text, &|text| apply_tree(todo_list, root, text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> TODOList {
+        let due_date = Local.ymd(2030, 3, 4).and_hms(17, 30, 0);
+        let mut parent = Task::new(String::from("Fix issue #42"), String::from("Notes"), Priority::High, due_date);
+        parent.add_subtask(Task::new(String::from("Call Bob (due back Monday)"), String::new(), Priority::Medium, due_date));
+        parent.add_subtask(Task::new(String::from("Write tests"), String::new(), Priority::Medium, due_date));
+        TODOList { tasks: vec![parent], tags: Vec::new() }
+    }
+
+    fn outline(todo_list: &TODOList) -> String {
+        outline::export_outline(&todo_list.tasks.iter().collect::<Vec<_>>(), todo_list)
+    }
+
+    #[test]
+    fn unchanged_outline() {
+        let todo_list = sample();
+        let changes = apply_tree(&todo_list, None, &outline(&todo_list)).unwrap();
+        assert!(changes.problems.is_empty(), "{:?}", changes.problems);
+        assert_eq!((changes.kept, changes.added, changes.removed), (3, 0, 0));
+        assert_eq!(serde_json::to_string(&changes.todo_list).unwrap(), serde_json::to_string(&todo_list).unwrap());
+    }
+
+    #[test]
+    fn removed_due_dates_are_kept() {
+        let todo_list = sample();
+        let text = outline(&todo_list).replace(" (due 2030-03-04 17:30)", "");
+        let changes = apply_tree(&todo_list, None, &text).unwrap();
+        assert!(changes.todo_list.flattened().iter().all(|task| task.due_date == todo_list.tasks[0].due_date));
+    }
+
+    #[test]
+    fn subtasks_left_out_are_removed() {
+        let todo_list = sample();
+        let text = outline(&todo_list).replace("[ ]", "[x]") + "  - [x] New\n";
+        let changes = apply_tree(&todo_list, None, &text).unwrap();
+        assert_eq!(changes.problems.len(), 3, "{:?}", changes.problems);
+        assert_eq!(changes.todo_list.flattened().len(), 1);
+        assert_eq!((changes.kept, changes.added, changes.removed), (1, 0, 2));
+    }
+}
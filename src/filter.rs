@@ -0,0 +1,54 @@
+use chrono::Local;
+use crate::{Completion, Priority, Tag, Task};
+
+#[derive(Clone, Default)]
+pub struct Filter {
+    pub priorities: Vec<Priority>,
+    pub tags: Vec<String>,
+    pub max_days: Option<i64>,
+    pub complete: Option<bool>,
+    pub words: Vec<String>
+}
+
+impl Filter {
+    pub fn parse(expression: &str) -> Result<Filter, String> {
+        // Parses a filter expression such as "priority:high,medium tag:work due:3 report". Terms are separated by whitespace and must all match. Terms without a key are matched against task names.
+        let mut filter = Filter::default();
+        for term in expression.split_whitespace() {
+            match term.split_once(':') {
+                Some(("priority", value)) | Some(("p", value)) => {
+                    for priority in value.split(',') {
+                        filter.priorities.push(priority.parse::<Priority>()?);
+                    }
+                },
+                Some(("tag", value)) | Some(("t", value)) => {
+                    filter.tags.push(String::from(value));
+                },
+                Some(("due", value)) | Some(("d", value)) => {
+                    filter.max_days = Some(value.parse::<i64>().map_err(|_| {
+                        format!("Unexpected value in filter term \"{}\", expected [int].", term)
+                    })?);
+                },
+                Some(("status", "complete")) | Some(("s", "complete")) => filter.complete = Some(true),
+                Some(("status", "incomplete")) | Some(("s", "incomplete")) => filter.complete = Some(false),
+                Some(("status", value)) | Some(("s", value)) => {
+                    return Err(format!("Unknown status \"{}\", expected complete or incomplete.", value));
+                },
+                Some(("name", value)) | Some(("n", value)) => filter.words.push(value.to_lowercase()),
+                Some((key, _)) => return Err(format!("Unknown filter key \"{}\".", key)),
+                None => filter.words.push(term.to_lowercase())
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn matches(&self, task: &Task, tags: &[Tag]) -> bool {
+        (self.priorities.is_empty() || self.priorities.contains(&task.priority)) &&
+        self.tags.iter().all(|name| {
+            tags.iter().any(|tag| &tag.name == name && task.tags.contains(&tag.uuid))
+        }) &&
+        self.max_days.is_none_or(|days| days >= (task.due_date - Local::now()).num_days()) &&
+        self.complete.is_none_or(|complete| complete == matches!(task.completion, Completion::Complete)) &&
+        self.words.iter().all(|word| task.name.to_lowercase().contains(word.as_str()))
+    }
+}
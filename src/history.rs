@@ -0,0 +1,173 @@
+use std::{env, fs};
+use std::path::Path;
+use std::process::Command;
+use crate::config::Storage;
+
+fn git(list: &Path, args: &[&str]) -> Result<String, String> {
+    // Runs git in the directory holding the TODO list, returning what it printed, or what went wrong if it failed.
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(directory(list))
+        .env("GIT_EDITOR", "true")
+        .output()
+        .map_err(|_| String::from("git could not be run. Is it installed?"))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(format!("git {} failed: {}", args[0], String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+fn directory(list: &Path) -> &Path {
+    list.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."))
+}
+
+fn file_name(list: &Path) -> &str {
+    list.file_name().and_then(|name| name.to_str()).unwrap_or("list.json")
+}
+
+fn git_as_user(list: &Path, args: &[&str]) -> Result<String, String> {
+    // Runs a git command which records who made a commit. If no identity is set up, commits are made as faros rather than failing.
+    if git(list, &["config", "user.email"]).is_ok() {
+        git(list, args)
+    } else {
+        let mut with_identity = vec!["-c", "user.name=faros", "-c", "user.email=faros@localhost"];
+        with_identity.extend_from_slice(args);
+        git(list, &with_identity)
+    }
+}
+
+fn is_list(file: &str) -> bool {
+    // Lists are kept in files named after them, whose names hold no dots, unlike files kept alongside them such as list.caldav.json.
+    file.strip_suffix(".json").is_some_and(|name| !name.is_empty() && !name.contains('.'))
+}
+
+fn open(storage: &Storage, list: &Path) -> Result<(), String> {
+    // Makes sure the TODO list is in a git repository of its own, whose top level is the directory holding it, creating one there if it isn't, such as when the TODO list is kept within another project. New repositories are set up to merge TODO lists with faros merge, so that pulling changes made elsewhere merges them task by task, while other files are merged by git as usual.
+    let top_level = git(list, &["rev-parse", "--show-toplevel"]).ok().and_then(|dir| Path::new(dir.trim()).canonicalize().ok());
+    if top_level.is_some() && top_level == directory(list).canonicalize().ok() {
+        return Ok(());
+    }
+    git(list, &["init", "--quiet"])?;
+    git(list, &["symbolic-ref", "HEAD", &format!("refs/heads/{}", storage.branch)])?;
+    let faros = env::current_exe().map_err(|_| String::from("The faros executable couldn't be found."))?;
+    git(list, &["config", "merge.faros.name", "faros TODO list merge"])?;
+    git(list, &["config", "merge.faros.driver", &format!("'{}' merge %O %A %B", faros.display())])?;
+    let info = directory(list).join(".git").join("info");
+    fs::create_dir_all(&info)
+        .and_then(|_| fs::write(info.join("attributes"), format!("*.json merge=faros\n*.*.json !merge\n{} merge=faros\n", file_name(list))))
+        .map_err(|_| format!("The git repository in {} couldn't be set up.", directory(list).display()))
+}
+
+pub fn commit(storage: &Storage, lists: &[&Path], message: &str) -> Result<(), String> {
+    // Commits the TODO lists which have changed since the last commit, with one commit for each directory holding them.
+    let list = match lists.first() {
+        Some(list) => *list,
+        None => return Ok(())
+    };
+    let (here, elsewhere): (Vec<&Path>, Vec<&Path>) = lists.iter().partition(|other| directory(other) == directory(list));
+    open(storage, list)?;
+    let mut args = vec!["--"];
+    args.extend(here.iter().map(|other| file_name(other)));
+    if !git(list, &[&["status", "--porcelain"], &args[..]].concat())?.trim().is_empty() {
+        git(list, &[&["add"], &args[..]].concat())?;
+        git_as_user(list, &[&["commit", "--quiet", "--message", message], &args[..]].concat())?;
+    }
+    commit(storage, &elsewhere, message)
+}
+
+pub fn log(storage: &Storage, list: &Path, number: Option<&str>) -> Result<String, String> {
+    open(storage, list)?;
+    if git(list, &["rev-parse", "--verify", "--quiet", "HEAD"]).is_err() {
+        return Ok(String::new());
+    }
+    let mut args = vec!["log", "--date=format:%Y-%m-%d %H:%M", "--format=%h  %ad  %an  %s"];
+    if let Some(number) = number {
+        args.push("--max-count");
+        args.push(number);
+    }
+    args.extend_from_slice(&["--", file_name(list)]);
+    git(list, &args)
+}
+
+pub fn sync(storage: &Storage, list: &Path) -> Result<bool, String> {
+    // Rebases the history of the TODO list onto the remote's, and then pushes it to the remote. Changes made on both sides are merged task by task by faros merge, and where they conflict, the version already on the remote is kept, which is reported by returning true. Anything else that goes wrong leaves the history as it was.
+    let remote = storage.remote.as_deref().ok_or_else(|| {
+        String::from("No remote is set up. Add a remote to the [storage] section of your configuration file.")
+    })?;
+    open(storage, list)?;
+    let file = file_name(list);
+    match git(list, &["remote", "get-url", "origin"]) {
+        Ok(url) if url.trim() == remote => (),
+        Ok(_) => {
+            git(list, &["remote", "set-url", "origin", remote])?;
+        },
+        Err(_) => {
+            git(list, &["remote", "add", "origin", remote])?;
+        }
+    }
+    git(list, &["fetch", "--quiet", "origin"])?;
+
+    let upstream = format!("origin/{}", storage.branch);
+    let mut conflicted = false;
+    if git(list, &["rev-parse", "--verify", "--quiet", &upstream]).is_ok() {
+        if git(list, &["rev-parse", "--verify", "--quiet", "HEAD"]).is_err() {
+            git(list, &["reset", "--quiet", "--hard", &upstream])?;
+        } else {
+            let mut result = git_as_user(list, &["rebase", "--quiet", &upstream]);
+            // faros merge reports conflicts by failing, but still writes a merged TODO list, which is used as long as TODO lists are the only conflicted files.
+            while let Err(err) = result {
+                let unmerged = git(list, &["diff", "--name-only", "--relative", "--diff-filter=U"]).unwrap_or_default();
+                if unmerged.trim().is_empty() || !unmerged.lines().all(|f| f == file || is_list(f)) {
+                    let _ = git(list, &["rebase", "--abort"]);
+                    return Err(err);
+                }
+                conflicted = true;
+                git(list, &[&["add", "--"], &unmerged.lines().collect::<Vec<_>>()[..]].concat())?;
+                result = if git(list, &["diff", "--cached", "--quiet"]).is_ok() {
+                    git_as_user(list, &["rebase", "--skip"])
+                } else {
+                    git_as_user(list, &["rebase", "--continue"])
+                };
+            }
+        }
+    }
+    git(list, &["push", "--quiet", "origin", &format!("HEAD:refs/heads/{}", storage.branch)])?;
+    Ok(conflicted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repositories_of_their_own() {
+        let dir = env::temp_dir().join(format!("faros-history-{}", std::process::id()));
+        let project = dir.join("project");
+        let data = project.join("data");
+        fs::create_dir_all(&data).unwrap();
+        let (outer, list) = (project.join("README"), data.join("list.json"));
+        fs::write(&outer, "").unwrap();
+        git(&outer, &["init", "--quiet"]).unwrap();
+        fs::write(&list, "{}").unwrap();
+        fs::write(data.join("list.caldav.json"), "{}").unwrap();
+
+        // The TODO list is committed to a repository of its own in the data directory, rather than to the project holding it.
+        commit(&Storage::default(), &[&list], "add: Write tests").unwrap();
+        assert!(data.join(".git").is_dir());
+        assert!(git(&outer, &["rev-parse", "--verify", "--quiet", "HEAD"]).is_err());
+        assert_eq!(log(&Storage::default(), &list, None).unwrap().lines().count(), 1);
+
+        let merge = |file: &str| git(&list, &["check-attr", "merge", "--", file]).unwrap();
+        assert_eq!(merge("list.json"), "list.json: merge: faros\n");
+        assert_eq!(merge("work.json"), "work.json: merge: faros\n");
+        assert_eq!(merge("list.caldav.json"), "list.caldav.json: merge: unspecified\n");
+        assert!(is_list("work.json") && !is_list("list.caldav.json") && !is_list("README"));
+
+        // Once it has its own repository, that repository is used again.
+        fs::write(&list, "{\"tasks\": []}").unwrap();
+        commit(&Storage::default(), &[&list], "complete: Write tests").unwrap();
+        assert_eq!(log(&Storage::default(), &list, None).unwrap().lines().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,406 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+use crate::{Completion, Priority, TODOList, Task};
+use crate::render;
+
+const DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+#[derive(Clone)]
+pub struct Vtodo {
+    pub uuid: Uuid,
+    pub summary: String,
+    pub description: String,
+    pub due_date: Option<DateTime<Local>>,
+    pub priority: Priority,
+    pub categories: Vec<String>,
+    pub parent: Option<Uuid>,
+    pub status: String
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\')
+        }
+    }
+    unescaped
+}
+
+fn split_unescaped(text: &str) -> Vec<String> {
+    // Splits a list value, such as CATEGORIES, on the commas which aren't escaped.
+    let mut values = vec![String::new()];
+    let mut escaped = false;
+    for c in text.chars() {
+        match c {
+            ',' if !escaped => values.push(String::new()),
+            _ => values.last_mut().unwrap().push(c)
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    values.iter().map(|value| unescape(value)).filter(|value| !value.is_empty()).collect()
+}
+
+fn fold(line: &str) -> String {
+    // Content lines are folded so that no line is longer than 75 octets, as RFC 5545 requires. Continuation lines begin with a space.
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+pub fn uid_to_uuid(uid: &str) -> Uuid {
+    // UIDs written by faros are UUIDs, but other programs may use any string, so those are mapped onto UUIDs deterministically to keep repeated imports from duplicating tasks.
+    Uuid::parse_str(uid).unwrap_or_else(|_| Uuid::new_v5(&Uuid::NAMESPACE_URL, uid.as_bytes()))
+}
+
+pub fn vtodo(task: &Task, todo_list: &TODOList) -> String {
+    let path = todo_list.path(task.uuid);
+    let mut lines = vec![
+        String::from("BEGIN:VTODO"),
+        format!("UID:{}", task.uuid),
+        format!("DTSTAMP:{}", Utc::now().format(DATE_TIME_FORMAT)),
+        format!("SUMMARY:{}", escape(&task.name)),
+        format!("DUE:{}", task.due_date.with_timezone(&Utc).format(DATE_TIME_FORMAT)),
+        format!("PRIORITY:{}", match task.priority {
+            Priority::High => 1,
+            Priority::Medium => 5,
+            Priority::Low => 9
+        }),
+        format!("STATUS:{}", match task.completion {
+            Completion::Complete => "COMPLETED",
+            Completion::Incomplete(_) => "NEEDS-ACTION"
+        })
+    ];
+    if !task.description.is_empty() {
+        lines.push(format!("DESCRIPTION:{}", escape(&task.description)));
+    }
+    let tags = render::tag_names(task, &todo_list.tags);
+    if !tags.is_empty() {
+        lines.push(format!("CATEGORIES:{}", tags.iter().map(|tag| escape(tag)).collect::<Vec<_>>().join(",")));
+    }
+    if let Some(parent) = path.len().checked_sub(2).map(|i| path[i]) {
+        lines.push(format!("RELATED-TO;RELTYPE=PARENT:{}", parent.uuid));
+    }
+    lines.push(String::from("END:VTODO"));
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+pub fn calendar(components: &[String]) -> String {
+    format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{}{}END:VCALENDAR\r\n",
+            fold(&format!("PRODID:-//faros//faros {}//EN", crate_version!())),
+            components.concat())
+}
+
+pub fn export(todo_list: &TODOList) -> String {
+    let components = todo_list.flattened().into_iter().map(|task| vtodo(task, todo_list)).collect::<Vec<_>>();
+    calendar(&components)
+}
+
+fn parse_date(value: &str, parameters: &[&str]) -> Option<DateTime<Local>> {
+    // Dates may be in UTC, may be all-day dates, or may be local times, either floating or in a named time zone. Named time zones are treated as the local time zone, as faros has no time zone database.
+    if parameters.contains(&"VALUE=DATE") || value.len() == 8 {
+        NaiveDate::parse_from_str(value, "%Y%m%d").ok().map(crate::due_on)
+    } else if value.ends_with('Z') {
+        NaiveDateTime::parse_from_str(value, DATE_TIME_FORMAT).ok().map(|d| Utc.from_utc_datetime(&d).with_timezone(&Local))
+    } else {
+        NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok().and_then(|d| Local.from_local_datetime(&d).earliest())
+    }
+}
+
+pub fn parse(contents: &str) -> Result<Vec<Vtodo>, String> {
+    // Parses every VTODO component in an iCalendar file, ignoring any other components and properties.
+    let mut lines: Vec<String> = Vec::new();
+    for line in contents.lines() {
+        match line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')) {
+            Some(continuation) if !lines.is_empty() => lines.last_mut().unwrap().push_str(continuation),
+            _ => lines.push(String::from(line))
+        }
+    }
+
+    let mut vtodos = Vec::new();
+    let mut current: Option<Vtodo> = None;
+    let mut depth = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let (name, value) = match line.split_once(':') {
+            Some(property) => property,
+            None if line.trim().is_empty() => continue,
+            None => return Err(format!("line {}: \"{}\" is not a valid content line", i + 1, line))
+        };
+        let mut parameters = name.split(';');
+        let name = parameters.next().unwrap().to_uppercase();
+        let parameters = parameters.collect::<Vec<_>>();
+
+        match (name.as_str(), current.as_mut()) {
+            ("BEGIN", None) if value == "VTODO" => {
+                current = Some(Vtodo {
+                    uuid: Uuid::new_v4(),
+                    summary: String::new(),
+                    description: String::new(),
+                    due_date: None,
+                    priority: Priority::Medium,
+                    categories: Vec::new(),
+                    parent: None,
+                    status: String::from("NEEDS-ACTION")
+                });
+            },
+            ("BEGIN", Some(_)) => depth += 1,
+            ("END", Some(_)) if depth > 0 => depth -= 1,
+            ("END", Some(_)) => vtodos.push(current.take().unwrap()),
+            (_, Some(_)) if depth > 0 => (),
+            ("UID", Some(vtodo)) => vtodo.uuid = uid_to_uuid(value),
+            ("SUMMARY", Some(vtodo)) => vtodo.summary = unescape(value),
+            ("DESCRIPTION", Some(vtodo)) => vtodo.description = unescape(value),
+            ("DUE", Some(vtodo)) => {
+                vtodo.due_date = Some(parse_date(value, &parameters).ok_or_else(|| {
+                    format!("line {}: \"{}\" is not a valid date", i + 1, value)
+                })?);
+            },
+            ("PRIORITY", Some(vtodo)) => {
+                vtodo.priority = match value.trim().parse::<u8>() {
+                    Ok(1..=4) => Priority::High,
+                    Ok(6..=9) => Priority::Low,
+                    _ => Priority::Medium
+                };
+            },
+            ("CATEGORIES", Some(vtodo)) => vtodo.categories.extend(split_unescaped(value)),
+            ("RELATED-TO", Some(vtodo)) => {
+                let reltype = parameters.iter().find_map(|p| p.strip_prefix("RELTYPE=")).unwrap_or("PARENT");
+                if reltype.eq_ignore_ascii_case("PARENT") {
+                    vtodo.parent = Some(uid_to_uuid(value));
+                }
+            },
+            ("STATUS", Some(vtodo)) => vtodo.status = value.to_uppercase(),
+            _ => ()
+        }
+    }
+    Ok(vtodos)
+}
+
+pub fn apply(vtodo: &Vtodo, task: &mut Task, tags: Vec<Uuid>) -> Result<(), String> {
+    // Updates a task from a VTODO, given the UUIDs of the tags named by its categories. Only incomplete tasks whose subtasks are all complete can be marked complete.
+    task.name = vtodo.summary.clone();
+    task.description = vtodo.description.clone();
+    task.priority = vtodo.priority;
+    if let Some(due_date) = vtodo.due_date {
+        task.due_date = due_date;
+    }
+    task.tags = tags;
+    match (&task.completion, vtodo.status.as_str()) {
+        (Completion::Incomplete(children), "COMPLETED") => {
+            if children.iter().any(|child| matches!(child.completion, Completion::Incomplete(_))) {
+                return Err(format!("{}: it has incomplete subtasks, so it can't be marked complete", task.name));
+            }
+            task.completion = Completion::Complete;
+        },
+        (Completion::Complete, "NEEDS-ACTION") | (Completion::Complete, "IN-PROCESS") => {
+            task.completion = Completion::Incomplete(Vec::new());
+        },
+        _ => ()
+    }
+    Ok(())
+}
+
+pub fn import(contents: &str, todo_list: &mut TODOList) -> Result<Vec<String>, String> {
+    Ok(import_vtodos(parse(contents)?, todo_list))
+}
+
+pub fn import_vtodos(vtodos: Vec<Vtodo>, todo_list: &mut TODOList) -> Vec<String> {
+    // Adds VTODOs to the TODO list. VTODOs whose UIDs match tasks which are already in the list update those tasks rather than adding new ones, so that exporting, editing and importing again round-trips. New VTODOs are placed under their parents, which may be other new VTODOs or tasks already in the list. Only the first VTODO with each UID is used, as the others, such as the overridden occurrences of a recurring task, can't become tasks of their own.
+    let mut problems = Vec::new();
+    let mut new = HashMap::new();
+    let mut order = Vec::new();
+    let mut seen = HashSet::new();
+    for vtodo in vtodos {
+        if !seen.insert(vtodo.uuid) {
+            problems.push(format!("{}: an earlier task in the file has the same UID, so it was not imported", vtodo.summary));
+            continue;
+        }
+        if vtodo.status == "CANCELLED" {
+            problems.push(format!("{}: cancelled tasks are not imported", vtodo.summary));
+            continue;
+        }
+        let tags = vtodo.categories.iter().map(|tag| todo_list.tag_uuid(tag)).collect();
+        let result = match todo_list.task_with_uuid(vtodo.uuid) {
+            Some(task) => apply(&vtodo, task, tags),
+            None => {
+                let mut task = Task::new(String::new(), String::new(), vtodo.priority, vtodo.due_date.unwrap_or_else(crate::default_due_date));
+                task.uuid = vtodo.uuid;
+                let result = apply(&vtodo, &mut task, tags);
+                order.push(vtodo.uuid);
+                new.insert(vtodo.uuid, (task, vtodo.parent));
+                result
+            }
+        };
+        if let Err(problem) = result {
+            problems.push(problem);
+        }
+    }
+
+    // New tasks are added once their parents are in the list, so that subtasks can be imported before their parents. Anything left over has a parent which was never imported, or is part of a cycle, and becomes a top-level task.
+    let existing = todo_list.flattened().iter().map(|t| t.uuid).collect::<HashSet<_>>();
+    let mut added = existing;
+    loop {
+        let ready = order.iter()
+                         .copied()
+                         .filter(|uuid| new[uuid].1.is_none_or(|parent| added.contains(&parent) || !new.contains_key(&parent)))
+                         .collect::<Vec<_>>();
+        if ready.is_empty() {
+            break;
+        }
+        for uuid in ready {
+            let (task, parent) = new.remove(&uuid).unwrap();
+            order.retain(|u| *u != uuid);
+            added.insert(uuid);
+            match parent.and_then(|p| todo_list.task_with_uuid(p)) {
+                Some(parent) if matches!(parent.completion, Completion::Complete) && matches!(task.completion, Completion::Complete) => {
+                    problems.push(format!("{}: it is a subtask of {}, which is complete, so it was not imported", task.name, parent.name));
+                },
+                Some(parent) if matches!(parent.completion, Completion::Complete) => {
+                    problems.push(format!("{}: it is incomplete, but {} is complete, so it was made a top-level task", task.name, parent.name));
+                    todo_list.tasks.push(task);
+                },
+                Some(parent) => parent.add_subtask(task),
+                None => todo_list.tasks.push(task)
+            }
+        }
+    }
+    for uuid in order {
+        let (task, _) = new.remove(&uuid).unwrap();
+        problems.push(format!("{}: its parents form a cycle, so it was made a top-level task", task.name));
+        todo_list.tasks.push(task);
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty() -> TODOList {
+        TODOList { tasks: Vec::new(), tags: Vec::new() }
+    }
+
+    fn sample() -> TODOList {
+        let mut todo_list = empty();
+        let due_date = Local.ymd(2030, 3, 4).and_hms(17, 30, 0);
+        let mut parent = Task::new(String::from("Plan; the trip, soon"), String::from("Line one\nLine two \\ back"), Priority::High, due_date);
+        parent.tags = vec![todo_list.tag_uuid("travel, abroad")];
+        let mut child = Task::new(String::from("Book a hotel with a very long name which needs to be folded onto more than one line"), String::new(), Priority::Low, due_date);
+        child.completion = Completion::Complete;
+        parent.add_subtask(child);
+        todo_list.tasks.push(parent);
+        todo_list
+    }
+
+    #[test]
+    fn round_trip() {
+        let todo_list = sample();
+        let exported = export(&todo_list);
+        assert!(exported.split("\r\n").all(|line| line.len() <= 75));
+
+        let mut imported = empty();
+        let problems = import(&exported, &mut imported).unwrap();
+        assert!(problems.is_empty(), "{:?}", problems);
+        let (parent, original) = (&imported.tasks[0], &todo_list.tasks[0]);
+        assert_eq!(imported.tasks.len(), 1);
+        assert_eq!(parent.uuid, original.uuid);
+        assert_eq!(parent.name, original.name);
+        assert_eq!(parent.description, original.description);
+        assert_eq!(parent.due_date, original.due_date);
+        assert!(parent.priority == Priority::High);
+        assert_eq!(render::tag_names(parent, &imported.tags), ["travel, abroad"]);
+        let child = &parent.children()[0];
+        assert_eq!(child.name, original.children()[0].name);
+        assert!(matches!(child.completion, Completion::Complete));
+        assert!(child.priority == Priority::Low);
+    }
+
+    #[test]
+    fn importing_again_updates_tasks() {
+        let mut todo_list = sample();
+        let exported = export(&todo_list).replace("SUMMARY:Plan", "SUMMARY:Replan");
+        let problems = import(&exported, &mut todo_list).unwrap();
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(todo_list.flattened().len(), 2);
+        assert!(todo_list.tasks[0].name.starts_with("Replan"));
+    }
+
+    #[test]
+    fn other_programs() {
+        let contents = "BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nUID:child@example.com\r\nSUMMARY:Child\r\nRELATED-TO:parent@example.com\r\nDUE;VALUE=DATE:20300102\r\n\
+                        BEGIN:VALARM\r\nSUMMARY:Not the task\r\nEND:VALARM\r\nEND:VTODO\r\n\
+                        BEGIN:VTODO\r\nUID:parent@example.com\r\nSUMMARY:Parent\r\nPRIORITY:2\r\nEND:VTODO\r\n\
+                        BEGIN:VTODO\r\nUID:gone@example.com\r\nSUMMARY:Gone\r\nSTATUS:CANCELLED\r\nEND:VTODO\r\nEND:VCALENDAR\r\n";
+        let mut todo_list = empty();
+        let problems = import(contents, &mut todo_list).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(todo_list.tasks.len(), 1);
+        assert_eq!(todo_list.tasks[0].uuid, uid_to_uuid("parent@example.com"));
+        assert!(todo_list.tasks[0].priority == Priority::High);
+        let child = &todo_list.tasks[0].children()[0];
+        assert_eq!(child.name, "Child");
+        assert_eq!(child.due_date, crate::due_on(NaiveDate::from_ymd(2030, 1, 2)));
+    }
+
+    #[test]
+    fn malformed_input() {
+        let mut todo_list = empty();
+        assert!(import("BEGIN:VTODO\nnot a property\nEND:VTODO\n", &mut todo_list).is_err());
+        assert!(import("BEGIN:VTODO\nSUMMARY:Late\nDUE:someday\nEND:VTODO\n", &mut todo_list).is_err());
+        assert!(todo_list.tasks.is_empty());
+
+        let cycle = "BEGIN:VTODO\nUID:a\nSUMMARY:A\nRELATED-TO:b\nEND:VTODO\nBEGIN:VTODO\nUID:b\nSUMMARY:B\nRELATED-TO:a\nEND:VTODO\n";
+        let problems = import(cycle, &mut todo_list).unwrap();
+        assert_eq!(problems.len(), 2);
+        assert_eq!(todo_list.tasks.len(), 2);
+    }
+
+    #[test]
+    fn subtasks_of_complete_tasks() {
+        let contents = "BEGIN:VTODO\nUID:a\nSUMMARY:A\nSTATUS:COMPLETED\nEND:VTODO\n\
+                        BEGIN:VTODO\nUID:b\nSUMMARY:B\nSTATUS:COMPLETED\nRELATED-TO:a\nEND:VTODO\n\
+                        BEGIN:VTODO\nUID:c\nSUMMARY:C\nRELATED-TO:a\nEND:VTODO\n";
+        let mut todo_list = empty();
+        let problems = import(contents, &mut todo_list).unwrap();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems.iter().any(|problem| problem.starts_with("B:")));
+        assert_eq!(todo_list.tasks.iter().map(|task| task.name.as_str()).collect::<Vec<_>>(), ["A", "C"]);
+    }
+
+    #[test]
+    fn repeated_uids() {
+        let contents = "BEGIN:VTODO\nUID:a\nSUMMARY:Weekly\nEND:VTODO\n\
+                        BEGIN:VTODO\nUID:a\nRECURRENCE-ID:20300107T090000\nSUMMARY:Moved\nEND:VTODO\n\
+                        BEGIN:VTODO\nUID:b\nSUMMARY:Child\nRELATED-TO:a\nEND:VTODO\n";
+        let mut todo_list = empty();
+        let problems = import(&format!("{}{}", contents, contents), &mut todo_list).unwrap();
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert_eq!(todo_list.flattened().len(), 2);
+        assert_eq!(todo_list.tasks[0].name, "Weekly");
+        assert_eq!(todo_list.tasks[0].children()[0].name, "Child");
+    }
+}
@@ -0,0 +1,76 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::{Completion, TODOList, Tag, Task};
+
+pub const DEFAULT: &str = "default";
+
+pub fn validate(name: &str) -> Result<(), String> {
+    // List names become file names, so they are kept to letters, digits, hyphens and underscores. "list" is taken by the default list's file, list.json.
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        Err(format!("\"{}\" is not a valid list name, as list names may only contain letters, digits, hyphens and underscores.", name))
+    } else if name == "list" {
+        Err(String::from("\"list\" can't be used as a list name, as the default list is kept in list.json."))
+    } else {
+        Ok(())
+    }
+}
+
+pub fn path(dir: &Path, name: &str) -> PathBuf {
+    // Each list is kept in the data directory, in a file named after it. The default list keeps the name older versions of faros gave the only list.
+    if name == DEFAULT {
+        dir.join("list.json")
+    } else {
+        dir.join(format!("{}.json", name))
+    }
+}
+
+pub fn exists(dir: &Path, name: &str) -> bool {
+    name == DEFAULT || path(dir, name).exists()
+}
+
+pub fn names(dir: &Path) -> Vec<String> {
+    // Finds every list in the data directory, skipping files which only sit alongside lists, such as CalDAV sync state.
+    let mut names = fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|entry| entry.ok())
+                              .filter_map(|entry| entry.file_name().to_str().and_then(|f| f.strip_suffix(".json")).map(String::from))
+                              .filter(|name| name != "list" && validate(name).is_ok())
+                              .collect::<Vec<_>>())
+        .unwrap_or_default();
+    names.sort();
+    names.insert(0, String::from(DEFAULT));
+    names
+}
+
+pub fn current(dir: &Path) -> String {
+    // The list chosen with `faros use`, which is remembered in the data directory.
+    fs::read_to_string(dir.join("current"))
+        .ok()
+        .map(|name| String::from(name.trim()))
+        .filter(|name| exists(dir, name))
+        .unwrap_or_else(|| String::from(DEFAULT))
+}
+
+pub fn set_current(dir: &Path, name: &str) -> Result<(), String> {
+    fs::create_dir_all(dir)
+        .and_then(|_| fs::write(dir.join("current"), format!("{}\n", name)))
+        .map_err(|_| format!("{} could not be written to.", dir.join("current").display()))
+}
+
+pub fn carry_tags(task: &mut Task, tags: &[Tag], to: &mut TODOList) {
+    // Prepares a task and its subtasks, which are being moved from a list with the given tags, to be added to another list. Their tags are replaced by the other list's tags with the same names, and are added to the other list if it has no such tags.
+    task.tags = task.tags.iter()
+                         .filter_map(|uuid| tags.iter().find(|tag| tag.uuid == *uuid))
+                         .map(|tag| match to.tags.iter().find(|other| other.uuid == tag.uuid || other.name == tag.name) {
+                             Some(other) => other.uuid,
+                             None => {
+                                 to.tags.push(tag.clone());
+                                 tag.uuid
+                             }
+                         })
+                         .collect();
+    if let Completion::Incomplete(children) = &mut task.completion {
+        for child in children {
+            carry_tags(child, tags, to);
+        }
+    }
+}
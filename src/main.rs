@@ -1,105 +1,168 @@
 #[macro_use]
 extern crate clap;
 
-use chrono::{DateTime, Datelike, Timelike, Local, TimeZone};
+mod alias;
+mod caldav;
+mod completion;
+mod config;
+mod csv_import;
+mod edit;
+mod filter;
+mod history;
+mod ical;
+mod lists;
+mod merge;
+mod outline;
+mod output;
+mod render;
+mod search;
+mod shell;
+mod taskwarrior;
+mod todotxt;
+mod tree;
+mod tui;
+
+use chrono::{DateTime, Datelike, Timelike, Local, NaiveDate, NaiveDateTime, TimeZone};
 use serde::{Serialize, Deserialize};
-use serde_json;
-use std::{io, env, path, fs, fmt};
-use std::io::{Write};
-use clap::{App, Arg, ArgMatches};
+use std::{io, env, path, fs, fmt, str};
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use clap::{App, AppSettings, Arg, ArgMatches, ErrorKind, Shell};
 use uuid::Uuid;
+use config::{Backend, Config, Sort, View};
+use filter::Filter;
+use output::{Format, TaskRecord};
+use search::Matcher;
+use tree::TreeOptions;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 struct TODOList {
     tasks: Vec<Task>,
     tags: Vec<Tag>
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 enum Completion {
     Complete,
     Incomplete(Vec<Task>)
 }
 
 impl TODOList {
-    fn read() -> TODOList {
-        // Reads the TODOList from ~/.config/faros/list.json, creating anything that does not exist. If serializing the TODOList struct fails due to an unexpected EOF, we assume that the file is empty and return a new empty TODOList.
-        fs::create_dir_all(path::Path::new(&env::var_os("HOME").unwrap_or_else(|| {
-            eprintln!("$HOME environment variable does not exist.");
-            std::process::exit(1);
-        })).join(".config")
-           .join("faros")).unwrap_or_else(|_| {
-               eprintln!("~/.config/faros does not exist and could not be created.");
-               std::process::exit(1);
-           });
+    fn read(path: &path::Path) -> Result<TODOList, String> {
+        // Reads the TODOList from the given file, creating anything that does not exist. If serializing the TODOList struct fails due to an unexpected EOF, we assume that the file is empty and return a new empty TODOList.
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|_| format!("{} does not exist and could not be created.", dir.display()))?;
+        }
         serde_json::from_reader(io::BufReader::new(
-            fs::OpenOptions::new().read(true).write(true).create(true).open(
-                path::Path::new(&env::var_os("HOME").unwrap()).join(".config").join("faros").join("list.json"))
-                .unwrap_or_else(|_| {
-                    eprintln!("~/config/faros/list.json could not be opened, or could not be created if it doesn't exist.");
-                    std::process::exit(1);
-                }))).unwrap_or_else(|err| {
+            fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)
+                .map_err(|_| format!("{} could not be opened, or could not be created if it doesn't exist.", path.display()))?)).or_else(|err| {
                     match err.classify() {
-                        serde_json::error::Category::Eof => TODOList{tasks: Vec::new(), tags: Vec::new()},
-                        _ => {
-                            eprintln!("~/config/faros/list.json exists, but couldn't be parsed.");
-                            std::process::exit(1);
-                        }
+                        serde_json::error::Category::Eof => Ok(TODOList{tasks: Vec::new(), tags: Vec::new()}),
+                        _ => Err(format!("{} exists, but couldn't be parsed.", path.display()))
                     }
                 })
     }
 
-    fn write(self) {
-        // Write a TODOList to ~/.config/faros/list.json. This method assumes that the file already exists, and will not create it if it does not.
-        serde_json::to_writer(fs::OpenOptions::new().write(true).open(
-            path::Path::new(&env::var_os("HOME").unwrap()).join(".config").join("faros").join("list.json"))
-            .unwrap_or_else(|_| {
-                eprintln!("~/.config/faros/list.json could not be written to.");
-                std::process::exit(1);
-            }), 
-            &self).unwrap_or_else(|_| {
-                eprintln!("Your TODO list could not be serialized.");
-                std::process::exit(1);
-            });
+    fn write(&self, path: &path::Path) -> Result<(), String> {
+        // Write a TODOList to the given file. This method assumes that the file already exists, and will not create it if it does not.
+        serde_json::to_writer(fs::OpenOptions::new().write(true).truncate(true).open(path)
+            .map_err(|_| format!("{} could not be written to.", path.display()))?, 
+            self).map_err(|_| String::from("Your TODO list could not be serialized."))
     }
 
-    fn task_from_name(&mut self, name: &str) -> Option<&mut Task> {
-        // Get a mutable reference to a task in the TODO list with the given name, dealing with the fact that there may be multiple tasks with the same name. Return None if there are no tasks with the given name.
-        let mut tasks = self.flattened();
-        tasks.retain(|t| t.name.as_str() == name);
+    fn read_from(path: &str) -> Result<TODOList, String> {
+        // Reads a TODOList from any file, such as one of the versions of the list being merged. As with read, an empty file is an empty TODOList.
+        let contents = fs::read_to_string(path).map_err(|_| format!("{} could not be read.", path))?;
+        if contents.trim().is_empty() {
+            return Ok(TODOList { tasks: Vec::new(), tags: Vec::new() });
+        }
+        serde_json::from_str(&contents).map_err(|_| format!("{} exists, but couldn't be parsed.", path))
+    }
+
+    fn write_to(&self, path: &str) -> Result<(), String> {
+        let contents = serde_json::to_string(self).map_err(|_| String::from("Your TODO list could not be serialized."))?;
+        fs::write(path, contents).map_err(|_| format!("{} could not be written to.", path))
+    }
+
+    fn task_from_name(&mut self, name: &str) -> Result<Option<&mut Task>, String> {
+        // Get a mutable reference to a task in the TODO list with the given name, dealing with the fact that there may be multiple tasks with the same name by asking which one is meant. Return None if there are no matching tasks, and an error if the question couldn't be answered.
+        let tasks = self.tasks_named(name);
+        if tasks.is_empty() && io::stdin().is_terminal() && prompting() {
+            let matcher = Matcher::fuzzy(name);
+            let mut candidates = self.flattened()
+                                     .into_iter()
+                                     .filter_map(|t| matcher.find(&t.name).map(|(score, _)| (score, t)))
+                                     .collect::<Vec<_>>();
+            candidates.sort_by_key(|(score, _)| -score);
+            candidates.truncate(5);
+            if !candidates.is_empty() {
+                println!("There is no task in your TODO list named {}. Did you mean one of these? Select one, or press enter to cancel.", name);
+                for (i, (_, task)) in candidates.iter().enumerate() {
+                    println!("{}: {} ({})", i, task.name, task.short_id());
+                }
+                let buffer = read_line()?;
+                if buffer.is_empty() {
+                    return Ok(None);
+                }
+                let index = buffer.parse::<usize>().map_err(|_| format!("Error: Unexpected value, expected [int]. found\"{}\".", buffer))?;
+                if index >= candidates.len() {
+                    return Err(format!("Expected a value less than {}, found {}", candidates.len(), index));
+                }
+                let uuid = candidates[index].1.uuid;
+                return Ok(Some(self.task_from_uuid(uuid)));
+            }
+        }
         let uuid = match tasks.len() {
             0 => None,
             1 => Some(tasks[0].uuid),
+            _ if !prompting() => {
+                let mut message = format!("There is more than one task in your TODO list named {}. Name one of them by its ID or path instead:", name);
+                for task in tasks {
+                    message.push_str(&format!("\n    {} ({})", self.path(task.uuid).iter().map(|t| t.name.as_str()).collect::<Vec<_>>().join("/"), task.short_id()));
+                }
+                return Err(message);
+            },
             n => {
                 println!("There is more than one task in your TODO list named {}. Select one.", name);
-                for task in &tasks {
-                    println!("{}", task);
+                for (i, task) in tasks.iter().enumerate() {
+                    println!("{}: {}", i, task);
                 }
-                let mut buffer = String::new();
-                io::stdin().read_line(&mut buffer);
-                let index = buffer.trim().parse::<usize>().unwrap_or_else(|_| {
-                    eprintln!("Error: Unexpected value, expected [int]. found\"{}\".", buffer.trim());
-                    std::process::exit(1);
-                });
+                let buffer = read_line()?;
+                let index = buffer.trim().parse::<usize>().map_err(|_| format!("Error: Unexpected value, expected [int]. found\"{}\".", buffer.trim()))?;
                 if index >= n {
-                    eprintln!("Expected a value less than {}, found {}", n, index);
-                    std::process::exit(1);
+                    return Err(format!("Expected a value less than {}, found {}", n, index));
                 }
                 Some(tasks[index].uuid)
             }
         };
 
-        uuid.map(move |u| self.task_from_uuid(u))
+        Ok(uuid.map(move |u| self.task_from_uuid(u)))
     }
 
-    fn task_from_uuid(&mut self, uuid: Uuid) -> &mut Task {
-        for task in &mut self.tasks {
-            if let Some(valid_task) = task.task_from_uuid(uuid) {
-                return valid_task;
-            }
+    fn tasks_named(&self, name: &str) -> Vec<&Task> {
+        // Finds every task with the given name, without asking anything. If no task has the given name, it is instead treated as a UUID, a prefix of a UUID (such as a short ID) or a path of names separated by slashes.
+        let mut tasks = self.flattened();
+        tasks.retain(|t| t.name.as_str() == name);
+        if tasks.is_empty() {
+            let id = name.to_lowercase().replace('-', "");
+            tasks = self.flattened();
+            tasks.retain(|t| {
+                id.len() >= 4 && t.uuid.to_simple().to_string().starts_with(&id) ||
+                self.path(t.uuid).iter().map(|p| p.name.as_str()).collect::<Vec<_>>().join("/") == name
+            });
         }
+        tasks
+    }
+
+    fn task_from_uuid(&mut self, uuid: Uuid) -> &mut Task {
+        self.task_with_uuid(uuid).unwrap_or_else(|| {
+            panic!("If you're seeing this, Morgan REALLY fucked up.");
+        })
+    }
 
-        panic!("If you're seeing this, Morgan REALLY fucked up.");
+    fn task_with_uuid(&mut self, uuid: Uuid) -> Option<&mut Task> {
+        // Like task_from_uuid, but for UUIDs which may not be in the TODO list, such as those in imported files.
+        self.tasks.iter_mut().find_map(|task| task.task_from_uuid(uuid))
     }
 
     fn flattened(&self) -> Vec<&Task> {
@@ -110,14 +173,33 @@ impl TODOList {
         tasks
     }
 
+    fn tag_uuid(&mut self, name: &str) -> Uuid {
+        // Returns the UUID of the tag with the given name, creating the tag if it doesn't exist yet.
+        match self.tags.iter().find(|tag| tag.name == name) {
+            Some(tag) => tag.uuid,
+            None => {
+                let tag = Tag::new(String::from(name), String::new());
+                let uuid = tag.uuid;
+                self.tags.push(tag);
+                uuid
+            }
+        }
+    }
+
+    fn path(&self, uuid: Uuid) -> Vec<&Task> {
+        // Returns the chain of tasks from a top-level task down to the task with the given UUID, or an empty Vec if there is no such task.
+        self.tasks.iter().find_map(|task| task.path(uuid)).unwrap_or_default()
+    }
+
     fn remove_uuid(&mut self, uuid: Uuid) {
+        self.tasks.retain(|task| task.uuid != uuid);
         for task in &mut self.tasks {
             task.remove_uuid(uuid);
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 struct Task {
     name: String,
     description: String,
@@ -129,24 +211,55 @@ struct Task {
 
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 struct Tag {
     name: String,
     description: String,
     uuid: Uuid
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 enum Priority {
     High,
     Medium,
     Low
 }
 
-// This is a temporary, functional implementation. It still needs to be made pretty.
+impl str::FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Priority, String> {
+        match s.to_lowercase().as_str() {
+            "high" | "h" => Ok(Priority::High),
+            "medium" | "m" => Ok(Priority::Medium),
+            "low" | "l" => Ok(Priority::Low),
+            _ => Err(format!("Unknown priority \"{}\", expected high, medium or low.", s))
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Priority::High => write!(f, "high"),
+            Priority::Medium => write!(f, "medium"),
+            Priority::Low => write!(f, "low")
+        }
+    }
+}
+
 impl fmt::Display for Task {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Name: {}\n\tDescription: {}\n\tDue Date: {}\n\t{}", self.name, self.description, self.due_date, self.uuid)
+        write!(f, "{} ({})\n    Due:         {} ({})\n    Priority:    {}",
+               self.name,
+               self.short_id(),
+               self.due_date.format(&config::defaults().date_format),
+               render::relative_due(self.due_date, Local::now()),
+               self.priority)?;
+        if !self.description.is_empty() {
+            write!(f, "\n    Description: {}", self.description)?;
+        }
+        Ok(())
     }
 }
 
@@ -174,6 +287,34 @@ impl Task {
         tasks
     }
 
+    fn short_id(&self) -> String {
+        self.uuid.to_simple().to_string()[..8].to_string()
+    }
+
+    fn children(&self) -> &[Task] {
+        match &self.completion {
+            Completion::Complete => &[],
+            Completion::Incomplete(children) => children
+        }
+    }
+
+    fn progress(&self) -> (usize, usize) {
+        // Counts the complete subtasks of a task, and the total number of subtasks, at any depth.
+        let subtasks = self.flattened().into_iter().skip(1).collect::<Vec<_>>();
+        (subtasks.iter().filter(|t| matches!(t.completion, Completion::Complete)).count(), subtasks.len())
+    }
+
+    fn path(&self, uuid: Uuid) -> Option<Vec<&Task>> {
+        if self.uuid == uuid {
+            Some(vec![self])
+        } else {
+            self.children().iter().find_map(|child| child.path(uuid)).map(|mut path| {
+                path.insert(0, self);
+                path
+            })
+        }
+    }
+
     fn task_from_uuid(&mut self, uuid:Uuid) -> Option<&mut Task> {
         if self.uuid == uuid {
             Some(self)
@@ -192,36 +333,36 @@ impl Task {
         }
     }
 
-    fn complete(&mut self) {
+    fn add_subtask(&mut self, task: Task) {
+        // Adding a subtask to a complete task makes it incomplete again.
+        match &mut self.completion {
+            Completion::Complete => self.completion = Completion::Incomplete(vec![task]),
+            Completion::Incomplete(children) => children.push(task)
+        }
+    }
+
+    fn complete(&mut self) -> Result<(), String> {
         match &self.completion {
             Completion::Complete => {
                 println!("The task named {} is already marked as complete.", self.name)
             },
             Completion::Incomplete(children) => {
                 if children.iter().any(|child| matches!(child.completion, Completion::Incomplete(_))) {
-                    eprintln!("The task named {} cannot be completed, as it has incomplete subtask(s).", self.name);
-                    std::process::exit(1);
+                    return Err(format!("The task named {} cannot be completed, as it has incomplete subtask(s).", self.name));
                 } else {
                     self.completion = Completion::Complete;
                 }
             }
         }
-    }
-
-    fn valid(&self, high: bool, medium: bool, low: bool, max_days: i64) -> bool {
-        if high || medium || low {
-            (matches!(self.priority, Priority::High) && high ||
-            matches!(self.priority, Priority::Medium) && medium ||
-            matches!(self.priority, Priority::Low) && low) &&
-            (max_days >= (self.due_date - Local::now()).num_days())
-        } else {
-            self.valid(true, true, true, max_days)
-        }
+        Ok(())
     }
 
     fn remove_uuid(&mut self, uuid: Uuid) {
         if let Completion::Incomplete(children) = &mut self.completion {
             children.retain(|task| task.uuid != uuid);
+            for child in children {
+                child.remove_uuid(uuid);
+            }
         }
     }
 }
@@ -235,11 +376,480 @@ impl Tag {
     }
 }
 
-fn cli() -> ArgMatches<'static> {
+fn base_dir(xdg: &str, fallback: &[&str]) -> Option<path::PathBuf> {
+    // Finds an XDG base directory, which is given by an environment variable holding an absolute path, or otherwise lies at a fixed place within $HOME.
+    env::var_os(xdg)
+        .map(path::PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| fallback.iter().fold(path::PathBuf::from(home), |dir, part| dir.join(part))))
+        .map(|dir| dir.join("faros"))
+}
+
+fn config_dir() -> Option<path::PathBuf> {
+    // The directory holding faros's configuration, usually ~/.config/faros.
+    base_dir("XDG_CONFIG_HOME", &[".config"])
+}
+
+fn data_dir() -> Option<path::PathBuf> {
+    // The directory holding the TODO list, usually ~/.local/share/faros. Older versions of faros kept the TODO list alongside the configuration, so if there is a TODO list there, and none in the data directory, it is used where it is.
+    let legacy = config_dir().filter(|dir| dir.join("list.json").exists());
+    let data = base_dir("XDG_DATA_HOME", &[".local", "share"]);
+    match (legacy, data) {
+        (Some(legacy), Some(data)) if !data.join("list.json").exists() => Some(legacy),
+        (legacy, data) => data.or(legacy)
+    }
+}
+
+fn list_path(file: Option<&str>, list: Option<&str>) -> Result<path::PathBuf, String> {
+    // The TODO list is read from the file given with --file, then the list named with --list, then the file named by $FAROS_DATA, and otherwise the list chosen with `faros use` in the data directory, which is list.json unless another list has been chosen.
+    if let Some(file) = file {
+        return Ok(path::PathBuf::from(file));
+    }
+    if let Some(list) = list {
+        return named_list(list);
+    }
+    env::var_os("FAROS_DATA").filter(|f| !f.is_empty()).map(path::PathBuf::from)
+        .or_else(|| data_dir().map(|dir| lists::path(&dir, &lists::current(&dir))))
+        .ok_or_else(|| String::from("There is nowhere to keep your TODO list, as $HOME is not set. Set $FAROS_DATA or use --file to choose a file."))
+}
+
+fn lists_dir() -> Result<path::PathBuf, String> {
+    data_dir().ok_or_else(|| String::from("There is nowhere to keep your lists, as $HOME is not set."))
+}
+
+fn named_list(name: &str) -> Result<path::PathBuf, String> {
+    // Finds the file holding one of the named lists in the data directory, which must already exist.
+    let dir = lists_dir()?;
+    lists::validate(name)?;
+    if !lists::exists(&dir, name) {
+        return Err(format!("There is no list named {}. Create it with `faros list-create {}`.", name, name));
+    }
+    Ok(lists::path(&dir, name))
+}
+
+fn global_value<'a>(matches: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    // Finds the value of a global argument, which may have been given before or after any of the subcommands.
+    let (_, subcommand) = matches.subcommand();
+    subcommand.and_then(|sub| global_value(sub, name)).or_else(|| matches.value_of(name))
+}
+
+fn due_on(date: NaiveDate) -> DateTime<Local> {
+    // Tasks given only a due date are due at the default due time, which is the end of that day unless the configuration file says otherwise.
+    local(date.and_time(config::defaults().due_time))
+}
+
+fn due_at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> Result<DateTime<Local>, String> {
+    // Builds a due date from the parts given on the command line, which may not make a date at all, such as the 31st of February or the 25th hour.
+    NaiveDate::from_ymd_opt(year, month, day).and_then(|date| date.and_hms_opt(hour, minute, 0))
+                                             .map(local)
+                                             .ok_or_else(|| format!("{}-{:02}-{:02} {:02}:{:02} is not a valid due date.", year, month, day, hour, minute))
+}
+
+fn local(time: NaiveDateTime) -> DateTime<Local> {
+    // A time in the hour skipped when the clocks go forward doesn't exist locally, so it is read as UTC instead.
+    Local.from_local_datetime(&time).earliest().unwrap_or_else(|| Local.from_utc_datetime(&time))
+}
+
+fn default_due_date() -> DateTime<Local> {
+    due_on(Local::today().naive_local())
+}
+
+fn read_import(path: &str) -> Result<String, String> {
+    // Reads a file to be imported, or standard input if the path is "-".
+    let mut contents = String::new();
+    let result = if path == "-" {
+        io::Read::read_to_string(&mut io::stdin(), &mut contents).map(|_| contents)
+    } else {
+        fs::read_to_string(path)
+    };
+    result.map_err(|_| format!("{} could not be read.", path))
+}
+
+fn write_export(output: Option<&str>, contents: &str) -> Result<(), String> {
+    // Writes exported tasks to the given file, or to standard output if no file is given.
+    match output {
+        Some(path) => fs::write(path, contents).map_err(|_| format!("{} could not be written to.", path)),
+        None => {
+            print!("{}", contents);
+            Ok(())
+        }
+    }
+}
+
+fn print_problems(problems: &[String]) {
+    if !problems.is_empty() {
+        eprintln!("Some of the imported file could not be mapped onto tasks:");
+        for problem in problems {
+            eprintln!("    {}", problem);
+        }
+    }
+}
+
+// Set while a batch is running, which never prompts, as its commands come from a script.
+static IN_BATCH: AtomicBool = AtomicBool::new(false);
+
+fn matching(todo_list: &TODOList, expression: &str) -> Result<Vec<Uuid>, String> {
+    // Finds every task matching a filter expression, for commands which change many tasks at once.
+    let filter = Filter::parse(expression).map_err(|err| format!("Error: {}", err))?;
+    Ok(todo_list.flattened().into_iter().filter(|task| filter.matches(task, &todo_list.tags)).map(|task| task.uuid).collect())
+}
+
+fn confirm_bulk(heading: &str, uuids: &[Uuid], todo_list: &TODOList, yes: bool) -> Result<bool, String> {
+    // Shows which tasks a command given a filter is about to change, and asks first if there are more of them than the configuration file allows without asking. Without a terminal to ask at, or with prompts turned off, --yes is needed instead.
+    println!("{}", heading);
+    for uuid in uuids {
+        let path = todo_list.path(*uuid);
+        println!("    {} ({})", path.iter().map(|t| t.name.as_str()).collect::<Vec<_>>().join("/"), path[path.len() - 1].short_id());
+    }
+    let limit = config::defaults().confirm_above;
+    if yes || uuids.len() <= limit {
+        return Ok(true);
+    }
+    if !prompting() || !io::stdin().is_terminal() {
+        return Err(format!("Nothing was changed, as this would change more than {} tasks. Add --yes to go ahead.", limit));
+    }
+    let answer = prompt(&format!("Change these {} tasks? [y/N] ", uuids.len()))?;
+    if matches!(answer.to_lowercase().as_str(), "y" | "yes") {
+        Ok(true)
+    } else {
+        println!("Nothing was changed.");
+        Ok(false)
+    }
+}
+
+fn complete_matching(todo_list: &mut TODOList, expression: &str, yes: bool) -> Result<(), String> {
+    // Completes every incomplete task matching a filter. A task can only be completed once its subtasks are, so tasks with incomplete subtasks which aren't being completed along with them are skipped.
+    fn completable(task: &Task, selected: &[Uuid]) -> bool {
+        selected.contains(&task.uuid) &&
+        task.children().iter().all(|child| matches!(child.completion, Completion::Complete) || completable(child, selected))
+    }
+    let selected = matching(todo_list, expression)?.into_iter()
+                                                  .filter(|uuid| matches!(todo_list.path(*uuid).last().unwrap().completion, Completion::Incomplete(_)))
+                                                  .collect::<Vec<_>>();
+    let (ready, blocked): (Vec<Uuid>, Vec<Uuid>) = selected.iter().partition(|uuid| completable(todo_list.path(**uuid).last().unwrap(), &selected));
+    for uuid in blocked {
+        eprintln!("{} has incomplete subtasks which don't match the filter, so it will be left incomplete.", todo_list.path(uuid).last().unwrap().name);
+    }
+    if ready.is_empty() {
+        println!("There are no incomplete tasks matching {} which can be completed.", expression);
+        return Ok(());
+    }
+    if !confirm_bulk(&format!("Completing {} task(s):", ready.len()), &ready, todo_list, yes)? {
+        return Ok(());
+    }
+    // Subtasks come after their parents, so going backwards completes them first.
+    for uuid in ready.iter().rev() {
+        todo_list.task_from_uuid(*uuid).completion = Completion::Complete;
+    }
+    println!("Completed {} task(s).", ready.len());
+    Ok(())
+}
+
+fn remove_matching(todo_list: &mut TODOList, expression: &str, yes: bool) -> Result<(), String> {
+    // Removes every task matching a filter. Subtasks go along with their parents, so those whose parents are being removed aren't listed separately.
+    let selected = matching(todo_list, expression)?;
+    let roots = selected.iter()
+                        .filter(|uuid| !todo_list.path(**uuid).iter().rev().skip(1).any(|parent| selected.contains(&parent.uuid)))
+                        .copied()
+                        .collect::<Vec<_>>();
+    if roots.is_empty() {
+        println!("There are no tasks matching {}.", expression);
+        return Ok(());
+    }
+    let subtasks = roots.iter().map(|uuid| todo_list.path(*uuid).last().unwrap().flattened().len() - 1).sum::<usize>();
+    let heading = if subtasks > 0 {
+        format!("Removing {} task(s), along with {} subtask(s) of them:", roots.len(), subtasks)
+    } else {
+        format!("Removing {} task(s):", roots.len())
+    };
+    if !confirm_bulk(&heading, &roots, todo_list, yes)? {
+        return Ok(());
+    }
+    for uuid in &roots {
+        todo_list.remove_uuid(*uuid);
+    }
+    println!("Removed {} task(s).", roots.len() + subtasks);
+    Ok(())
+}
+
+fn modify_matching(todo_list: &mut TODOList, app: &ArgMatches) -> Result<(), String> {
+    // Changes the priority and tags of every task matching a filter.
+    let expression = app.value_of("filter").unwrap();
+    let priority = app.value_of("priority").map(|p| p.parse::<Priority>().map_err(|err| format!("Error: {}", err))).transpose()?;
+    let add = app.values_of("add_tag").map(|tags| tags.collect::<Vec<_>>()).unwrap_or_default();
+    let remove = app.values_of("remove_tag").map(|tags| tags.collect::<Vec<_>>()).unwrap_or_default();
+    if priority.is_none() && add.is_empty() && remove.is_empty() {
+        return Err(String::from("Please say how to change the tasks matching the filter with --priority, --add-tag or --remove-tag."));
+    }
+    let selected = matching(todo_list, expression)?;
+    if selected.is_empty() {
+        println!("There are no tasks matching {}.", expression);
+        return Ok(());
+    }
+    if !confirm_bulk(&format!("Modifying {} task(s):", selected.len()), &selected, todo_list, app.is_present("yes"))? {
+        return Ok(());
+    }
+    let add = add.iter().map(|tag| todo_list.tag_uuid(tag)).collect::<Vec<_>>();
+    let remove = todo_list.tags.iter().filter(|tag| remove.contains(&tag.name.as_str())).map(|tag| tag.uuid).collect::<Vec<_>>();
+    for uuid in &selected {
+        let task = todo_list.task_from_uuid(*uuid);
+        if let Some(priority) = priority {
+            task.priority = priority;
+        }
+        task.tags.retain(|tag| !remove.contains(tag));
+        for tag in &add {
+            if !task.tags.contains(tag) {
+                task.tags.push(*tag);
+            }
+        }
+    }
+    println!("Modified {} task(s).", selected.len());
+    Ok(())
+}
+
+fn prompting() -> bool {
+    config::defaults().prompt && !IN_BATCH.load(Ordering::Relaxed)
+}
+
+fn read_line() -> Result<String, String> {
+    let mut buffer = String::new();
+    io::stdin().read_line(&mut buffer).map_err(|_| String::from("Could not read from standard input."))?;
+    Ok(String::from(buffer.trim()))
+}
+
+fn prompt(message: &str) -> Result<String, String> {
+    print!("{}", message);
+    io::stdout().flush().map_err(|_| String::from("Could not write to standard output."))?;
+    read_line()
+}
+
+fn prompt_for(thing: &str, field: &str) -> Result<String, String> {
+    // Asks for something which wasn't given on the command line. With prompts turned off in the configuration file, descriptions are left empty, and anything else is an error.
+    if prompting() {
+        prompt(&format!("Please give your new {} a {}: ", thing, field))
+    } else if field == "description" {
+        Ok(String::new())
+    } else {
+        Err(format!("Your new {} needs a {}, as prompts are turned off in your configuration file.", thing, field))
+    }
+}
+
+fn print_tasks(tasks: &[&Task], todo_list: &TODOList, view: &View) -> Result<(), String> {
+    match view.format {
+        Format::Table => render::print_table(tasks, &todo_list.tags, &view.columns),
+        Format::Long => {
+            for task in tasks {
+                println!("{}", task);
+            }
+        },
+        format => {
+            let records = tasks.iter().map(|task| TaskRecord::new(task, todo_list)).collect::<Vec<_>>();
+            return output::print_records(&records, format);
+        }
+    }
+    Ok(())
+}
+
+fn print_tree(roots: &[&Task], todo_list: &TODOList, keep: &dyn Fn(&Task) -> bool, options: &TreeOptions, format: Format) -> Result<(), String> {
+    if format.machine_readable() {
+        let records = roots.iter()
+                           .filter(|task| tree::retained(task, keep))
+                           .map(|task| TaskRecord::tree(task, todo_list, keep))
+                           .collect::<Vec<_>>();
+        output::print_records(&records, format)
+    } else {
+        tree::print_tree(roots, keep, options);
+        Ok(())
+    }
+}
+
+fn list(todo_list: &TODOList, view: &View, filter: &Filter, tree: bool) -> Result<(), String> {
+    if tree {
+        let roots = todo_list.tasks.iter().collect::<Vec<_>>();
+        return print_tree(&roots, todo_list, &|t| filter.matches(t, &todo_list.tags), &TreeOptions {
+            depth: None,
+            collapse_completed: false
+        }, view.format);
+    }
+
+    print_tasks(&selected(todo_list, view, filter), todo_list, view)
+}
+
+fn selected<'a>(todo_list: &'a TODOList, view: &View, filter: &Filter) -> Vec<&'a Task> {
+    let mut tasks = todo_list.flattened();
+    tasks.retain(|t| filter.matches(t, &todo_list.tags));
+    match view.sort.unwrap_or(config::defaults().sort) {
+        Sort::Due => tasks.sort_by_key(|t| t.due_date),
+        Sort::Priority => tasks.sort_by_key(|t| (t.priority, t.due_date)),
+        Sort::Name => tasks.sort_by(|t1, t2| t1.name.cmp(&t2.name))
+    }
+    tasks.truncate(view.limit.unwrap_or(tasks.len()));
+    tasks
+}
+
+fn list_all(view: &View, filter: &Filter, tree: bool) -> Result<(), String> {
+    // Lists tasks from every named list in turn, under a heading for each list, or as a single set of records marked with the lists they came from.
+    let dir = lists_dir()?;
+    let todo_lists = lists::names(&dir).into_iter()
+                                       .map(|name| {
+                                           let todo_list = TODOList::read(&lists::path(&dir, &name))?;
+                                           Ok((name, todo_list))
+                                       })
+                                       .collect::<Result<Vec<_>, String>>()?;
+    if view.format.machine_readable() {
+        let mut records = Vec::new();
+        for (name, todo_list) in &todo_lists {
+            let keep = |t: &Task| filter.matches(t, &todo_list.tags);
+            let mut found = if tree {
+                todo_list.tasks.iter()
+                               .filter(|task| tree::retained(task, &keep))
+                               .map(|task| TaskRecord::tree(task, todo_list, &keep))
+                               .collect::<Vec<_>>()
+            } else {
+                selected(todo_list, view, filter).iter().map(|task| TaskRecord::new(task, todo_list)).collect()
+            };
+            for record in &mut found {
+                record.in_list(name);
+            }
+            records.append(&mut found);
+        }
+        output::print_records(&records, view.format)
+    } else {
+        for (i, (name, todo_list)) in todo_lists.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!("{}:", name);
+            list(todo_list, view, filter, tree)?;
+        }
+        Ok(())
+    }
+}
+
+fn details(task: &Task, todo_list: &TODOList) -> Vec<String> {
+    // Lists every detail of a single task, as shown by `faros show` and in the TUI's detail pane. In faros, a task depends on its subtasks, since it can't be completed until they are, so a task is blocked by its incomplete subtasks and in turn blocks its parent.
+    let path = todo_list.path(task.uuid);
+    let (complete, total) = task.progress();
+    let status = match task.completion {
+        Completion::Complete => "complete",
+        Completion::Incomplete(_) => "incomplete"
+    };
+    let tags = render::tag_names(task, &todo_list.tags);
+    let blocked_by = task.children()
+                         .iter()
+                         .filter(|child| matches!(child.completion, Completion::Incomplete(_)))
+                         .map(|child| child.name.as_str())
+                         .collect::<Vec<_>>();
+
+    let mut lines = vec![
+        task.name.clone(),
+        format!("    ID:          {}", task.uuid),
+        format!("    Path:        {}", path.iter().map(|t| t.name.as_str()).collect::<Vec<_>>().join("/")),
+        format!("    Status:      {}", status),
+        format!("    Priority:    {}", task.priority),
+        format!("    Due:         {} ({})", task.due_date.format(&config::defaults().date_format), render::relative_due(task.due_date, Local::now())),
+        format!("    Tags:        {}", if tags.is_empty() { String::from("none") } else { tags.join(", ") }),
+        format!("    Subtasks:    {} open, {} complete", total - complete, complete)
+    ];
+    if !blocked_by.is_empty() {
+        lines.push(format!("    Blocked by:  {}", blocked_by.join(", ")));
+    }
+    if let (Some(parent), Completion::Incomplete(_)) = (path.len().checked_sub(2).map(|i| path[i]), &task.completion) {
+        lines.push(format!("    Blocks:      {}", parent.name));
+    }
+    if !task.description.is_empty() {
+        lines.push(String::new());
+        for line in task.description.lines() {
+            lines.push(String::from(format!("    {}", line).trim_end()));
+        }
+    }
+    if !task.children().is_empty() {
+        lines.push(String::new());
+        for child in task.children() {
+            let checkbox = match child.completion {
+                Completion::Complete => "[x]",
+                Completion::Incomplete(_) => "[ ]"
+            };
+            lines.push(format!("    {} {} ({})", checkbox, child.name, child.short_id()));
+        }
+    }
+    lines
+}
+
+fn show(task: &Task, todo_list: &TODOList) {
+    for line in details(task, todo_list) {
+        println!("{}", line);
+    }
+}
+
+fn cli() -> Result<ArgMatches<'static>, String> {
+    // Aliases defined in the configuration file are expanded before the command line is parsed.
+    let aliases = config::aliases();
+    if aliases.is_empty() {
+        return Ok(app().get_matches());
+    }
+    let args = alias::expand(env::args_os().map(|arg| arg.to_string_lossy().into_owned()).collect(), &aliases)?;
+    Ok(app().get_matches_from(args))
+}
+
+fn is_command(name: &str) -> bool {
+    // Checks whether a word names one of faros's commands, by asking clap whether it recognizes it.
+    match app().get_matches_from_safe(vec!["faros", name]) {
+        Ok(_) => true,
+        Err(err) => !matches!(err.kind, ErrorKind::UnknownArgument | ErrorKind::UnrecognizedSubcommand | ErrorKind::InvalidSubcommand)
+    }
+}
+
+// The commands which app() defines, along with their subcommands, for completing command names in the shell.
+const COMMANDS: [(&str, &[&str]); 26] = [
+    ("list", &[]),
+    ("tree", &[]),
+    ("view", &[]),
+    ("edit", &[]),
+    ("edit-tree", &[]),
+    ("tui", &[]),
+    ("shell", &[]),
+    ("batch", &[]),
+    ("show", &[]),
+    ("search", &[]),
+    ("complete", &[]),
+    ("add", &["subtask", "task", "tag"]),
+    ("modify", &["task", "tag"]),
+    ("import", &["csv", "todotxt", "taskwarrior", "ical", "markdown", "org"]),
+    ("export", &["todotxt", "taskwarrior", "ical", "markdown", "org"]),
+    ("caldav", &["sync"]),
+    ("merge", &[]),
+    ("log", &[]),
+    ("sync", &[]),
+    ("completions", &[]),
+    ("config", &["list", "get", "set"]),
+    ("lists", &[]),
+    ("list-create", &[]),
+    ("use", &[]),
+    ("move", &[]),
+    ("remove", &["task", "tag"])
+];
+
+fn app() -> App<'static, 'static> {
     App::new("faros")
             .author(crate_authors!())
             .version(crate_version!())
             .about("A simple CLI TODO list manager written in Rust.")
+            .arg(Arg::with_name("format")
+                               .long("format")
+                               .help("Specifies the output format: table, long, json, csv or tsv.")
+                               .takes_value(true)
+                               .global(true))
+            .arg(Arg::with_name("list_file")
+                               .long("file")
+                               .help("Specifies the file holding your TODO list, rather than the one in your data directory.")
+                               .takes_value(true)
+                               .global(true))
+            .arg(Arg::with_name("list_name")
+                               .long("list")
+                               .help("Specifies which of your named lists to use, rather than the one chosen with `faros use`.")
+                               .takes_value(true)
+                               .global(true))
             .subcommand(App::new("list")
                                 .about("Lists tasks from your TODO list.")
                                 .arg(Arg::with_name("days")
@@ -269,18 +879,97 @@ fn cli() -> ArgMatches<'static> {
                                                    .long("tag")
                                                    .help("Lists only tasks marked with the specified tag.")
                                                    .takes_value(true)
-                                                   .multiple(true)))
+                                                   .multiple(true))
+                                .arg(Arg::with_name("columns")
+                                                   .short("c")
+                                                   .long("columns")
+                                                   .help("Specifies the columns to show, separated by commas: id, status, name, description, due, priority, progress and tags.")
+                                                   .takes_value(true))
+                                .arg(Arg::with_name("long")
+                                                   .short("l")
+                                                   .long("long")
+                                                   .help("Lists every detail of each task, rather than a table."))
+                                .arg(Arg::with_name("tree")
+                                                   .long("tree")
+                                                   .help("Lists tasks as a tree, showing the parents of each task."))
+                                .arg(Arg::with_name("all_lists")
+                                                   .short("a")
+                                                   .long("all-lists")
+                                                   .help("Lists tasks from every one of your named lists, rather than only the current one.")))
+            .subcommand(App::new("tree")
+                                .about("Shows your TODO list as a tree of tasks and subtasks.")
+                                .arg(Arg::with_name("root")
+                                                   .help("Shows only the given task and its subtasks."))
+                                .arg(Arg::with_name("depth")
+                                                   .short("d")
+                                                   .long("depth")
+                                                   .help("Shows subtasks only up to the specified depth.")
+                                                   .takes_value(true))
+                                .arg(Arg::with_name("collapse")
+                                                   .short("c")
+                                                   .long("collapse")
+                                                   .help("Hides the subtasks of tasks whose subtasks are all complete.")))
+            .subcommand(App::new("view")
+                                .about("Lists tasks using a view defined in your configuration file.")
+                                .arg(Arg::with_name("view_name")
+                                                   .required(true)))
+            .subcommand(App::new("edit")
+                                .about("Edits a task's name, due date, priority, tags and description in your text editor.")
+                                .arg(Arg::with_name("task_name")
+                                                   .required(true)))
+            .subcommand(App::new("edit-tree")
+                                .about("Edits a task and its subtasks, or your whole TODO list, as an outline in your text editor, so that they can be reordered, reindented, renamed, added and removed.")
+                                .arg(Arg::with_name("task_name")))
+            .subcommand(App::new("tui")
+                                .about("Browses and changes your TODO list in a full-screen interface."))
+            .subcommand(App::new("shell")
+                                .about("Runs faros commands one line at a time, reading your TODO list once and saving it when you leave."))
+            .subcommand(App::new("batch")
+                                .about("Runs faros commands read from a file, one per line, all at once: if any of them fails, none of them are applied.")
+                                .arg(Arg::with_name("file")
+                                                   .help("The file to read commands from, or - for standard input, which is the default.")))
+            .subcommand(App::new("show")
+                                .about("Shows everything about a task.")
+                                .arg(Arg::with_name("task_name")
+                                                   .required(true)))
+            .subcommand(App::new("search")
+                                .about("Searches the names, descriptions and tags of tasks.")
+                                .arg(Arg::with_name("query")
+                                                   .required(true))
+                                .arg(Arg::with_name("regex")
+                                                   .short("r")
+                                                   .long("regex")
+                                                   .help("Treats the query as a regular expression.")
+                                                   .conflicts_with("fuzzy"))
+                                .arg(Arg::with_name("fuzzy")
+                                                   .short("f")
+                                                   .long("fuzzy")
+                                                   .help("Matches tasks containing the characters of the query in order, but not necessarily together."))
+                                .arg(Arg::with_name("number")
+                                                   .short("n")
+                                                   .long("number")
+                                                   .help("Shows a maximum of the specified number of results.")
+                                                   .takes_value(true)))
             .subcommand(App::new("complete")
                                 .about("Checks tasks off as complete.")
                                 .arg(Arg::with_name("task_name")
-                                                   .required(true)
-                                                   .multiple(true)))
+                                                   .required_unless("filter")
+                                                   .multiple(true))
+                                .arg(Arg::with_name("filter")
+                                                   .short("f")
+                                                   .long("filter")
+                                                   .help("Completes every incomplete task matching a filter expression, such as \"tag:sprint-3\", instead of tasks given by name.")
+                                                   .takes_value(true)
+                                                   .conflicts_with("task_name"))
+                                .arg(Arg::with_name("yes")
+                                                   .short("y")
+                                                   .long("yes")
+                                                   .help("Goes ahead without asking, however many tasks match the filter.")))
             .subcommand(App::new("add")
                                 .about("Adds something to your TODO list.")
                                 .subcommand(App::new("subtask")
-                                                    .about("Adds a subtask of an existing task to your TODO list.")
-                                                    .arg(Arg::with_name("parent_name")
-                                                                       .required(true))
+                                                    .about("Adds a subtask of an existing task to your TODO list. In faros shell, the parent defaults to the task chosen with cd.")
+                                                    .arg(Arg::with_name("parent_name"))
                                                     .arg(Arg::with_name("name")
                                                                        .short("n")
                                                                        .long("name")
@@ -379,6 +1068,36 @@ fn cli() -> ArgMatches<'static> {
                                                                        .takes_value(true))))
             .subcommand(App::new("modify")
                                 .about("Modifies something in your TODO list.")
+                                .setting(AppSettings::ArgsNegateSubcommands)
+                                .arg(Arg::with_name("filter")
+                                                   .short("f")
+                                                   .long("filter")
+                                                   .help("Modifies every task matching a filter expression, such as \"priority:low tag:work\".")
+                                                   .takes_value(true))
+                                .arg(Arg::with_name("yes")
+                                                   .short("y")
+                                                   .long("yes")
+                                                   .help("Goes ahead without asking, however many tasks match the filter."))
+                                .arg(Arg::with_name("priority")
+                                                   .short("p")
+                                                   .long("priority")
+                                                   .help("Gives the tasks matching the filter a priority: high, medium or low.")
+                                                   .takes_value(true)
+                                                   .requires("filter"))
+                                .arg(Arg::with_name("add_tag")
+                                                   .long("add-tag")
+                                                   .help("Adds a tag to the tasks matching the filter, creating it if it doesn't exist.")
+                                                   .takes_value(true)
+                                                   .multiple(true)
+                                                   .number_of_values(1)
+                                                   .requires("filter"))
+                                .arg(Arg::with_name("remove_tag")
+                                                   .long("remove-tag")
+                                                   .help("Removes a tag from the tasks matching the filter.")
+                                                   .takes_value(true)
+                                                   .multiple(true)
+                                                   .number_of_values(1)
+                                                   .requires("filter"))
                                 .subcommand(App::new("task")
                                                     .about("Modifies a task in your TODO list.")
                                                     .arg(Arg::with_name("task_name")
@@ -440,8 +1159,192 @@ fn cli() -> ArgMatches<'static> {
                                                                        .long("desc")
                                                                        .help("Specifies the tags's description.")
                                                                        .takes_value(true)))))
+            .subcommand(App::new("import")
+                                .about("Imports tasks into your TODO list from another format.")
+                                .subcommand(App::new("csv")
+                                                    .about("Imports a task for each row of a CSV file.")
+                                                    .arg(Arg::with_name("file")
+                                                                       .help("The file to import, or - for standard input.")
+                                                                       .required(true))
+                                                    .arg(Arg::with_name("map")
+                                                                       .short("m")
+                                                                       .long("map")
+                                                                       .help("Maps a field (name, description, due, priority, tags or parent) onto a column, as [field]=[column]. Fields are otherwise read from the columns with the same names.")
+                                                                       .takes_value(true)
+                                                                       .multiple(true)
+                                                                       .number_of_values(1))
+                                                    .arg(Arg::with_name("delimiter")
+                                                                       .long("delimiter")
+                                                                       .help("Specifies the character separating columns. Defaults to a comma.")
+                                                                       .takes_value(true))
+                                                    .arg(Arg::with_name("date_format")
+                                                                       .long("date-format")
+                                                                       .help("Specifies the format of due dates, such as %d/%m/%Y. Defaults to ISO 8601.")
+                                                                       .takes_value(true))
+                                                    .arg(Arg::with_name("under")
+                                                                       .short("u")
+                                                                       .long("under")
+                                                                       .help("Adds the imported tasks as subtasks of an existing task.")
+                                                                       .takes_value(true))
+                                                    .arg(Arg::with_name("dry_run")
+                                                                       .long("dry-run")
+                                                                       .help("Shows the tasks which would be imported, without importing them.")))
+                                .subcommand(App::new("todotxt")
+                                                    .about("Imports tasks from a todo.txt file.")
+                                                    .arg(Arg::with_name("file")
+                                                                       .help("The file to import, or - for standard input.")
+                                                                       .required(true)))
+                                .subcommand(App::new("taskwarrior")
+                                                    .about("Imports tasks from the output of Taskwarrior's `task export`.")
+                                                    .arg(Arg::with_name("file")
+                                                                       .help("The file to import, or - for standard input.")
+                                                                       .required(true))
+                                                    .arg(Arg::with_name("parent_uda")
+                                                                       .long("parent-uda")
+                                                                       .help("Specifies the UDA holding the UUID of each task's parent. Defaults to faros_parent.")
+                                                                       .takes_value(true)))
+                                .subcommand(App::new("ical")
+                                                    .about("Imports the VTODOs in an iCalendar file, updating tasks which were exported from faros.")
+                                                    .arg(Arg::with_name("file")
+                                                                       .help("The file to import, or - for standard input.")
+                                                                       .required(true)))
+                                .subcommand(App::new("markdown")
+                                                    .about("Imports tasks from a nested Markdown list or checklist.")
+                                                    .arg(Arg::with_name("file")
+                                                                       .help("The file to import, or - for standard input.")
+                                                                       .required(true)))
+                                .subcommand(App::new("org")
+                                                    .about("Imports tasks from org-mode headlines.")
+                                                    .arg(Arg::with_name("file")
+                                                                       .help("The file to import, or - for standard input.")
+                                                                       .required(true))))
+            .subcommand(App::new("export")
+                                .about("Exports your TODO list to another format.")
+                                .subcommand(App::new("todotxt")
+                                                    .about("Exports your tasks as a todo.txt file.")
+                                                    .arg(Arg::with_name("output")
+                                                                       .short("o")
+                                                                       .long("output")
+                                                                       .help("Specifies the file to write to, rather than standard output.")
+                                                                       .takes_value(true)))
+                                .subcommand(App::new("taskwarrior")
+                                                    .about("Exports your tasks as JSON that Taskwarrior's `task import` understands.")
+                                                    .arg(Arg::with_name("output")
+                                                                       .short("o")
+                                                                       .long("output")
+                                                                       .help("Specifies the file to write to, rather than standard output.")
+                                                                       .takes_value(true)))
+                                .subcommand(App::new("ical")
+                                                    .about("Exports your tasks as VTODOs in an iCalendar (.ics) file.")
+                                                    .arg(Arg::with_name("output")
+                                                                       .short("o")
+                                                                       .long("output")
+                                                                       .help("Specifies the file to write to, rather than standard output.")
+                                                                       .takes_value(true)))
+                                .subcommand(App::new("markdown")
+                                                    .about("Exports your tasks, or a task and its subtasks, as a nested Markdown checklist.")
+                                                    .arg(Arg::with_name("task_name")
+                                                                       .help("Exports only the given task and its subtasks."))
+                                                    .arg(Arg::with_name("output")
+                                                                       .short("o")
+                                                                       .long("output")
+                                                                       .help("Specifies the file to write to, rather than standard output.")
+                                                                       .takes_value(true)))
+                                .subcommand(App::new("org")
+                                                    .about("Exports your tasks, or a task and its subtasks, as org-mode headlines.")
+                                                    .arg(Arg::with_name("task_name")
+                                                                       .help("Exports only the given task and its subtasks."))
+                                                    .arg(Arg::with_name("output")
+                                                                       .short("o")
+                                                                       .long("output")
+                                                                       .help("Specifies the file to write to, rather than standard output.")
+                                                                       .takes_value(true))))
+            .subcommand(App::new("caldav")
+                                .about("Shares your TODO list with a CalDAV server.")
+                                .subcommand(App::new("sync")
+                                                    .about("Sends your changes to the CalDAV collection in your configuration file, and fetches changes made there.")
+                                                    .arg(Arg::with_name("conflicts")
+                                                                       .long("conflicts")
+                                                                       .help("Settles tasks changed both here and on the server: local keeps your changes, remote keeps the server's, and manual leaves both alone.")
+                                                                       .takes_value(true)
+                                                                       .possible_values(&["local", "remote", "manual"]))))
+            .subcommand(App::new("merge")
+                                .about("Merges two versions of a TODO list which were changed separately, such as on two computers.")
+                                .arg(Arg::with_name("base")
+                                                   .help("The version of the TODO list which both versions were changed from.")
+                                                   .required(true))
+                                .arg(Arg::with_name("ours")
+                                                   .help("Our version of the TODO list, which the merged list is written to.")
+                                                   .required(true))
+                                .arg(Arg::with_name("theirs")
+                                                   .help("Their version of the TODO list.")
+                                                   .required(true))
+                                .arg(Arg::with_name("output")
+                                                   .short("o")
+                                                   .long("output")
+                                                   .help("Specifies the file to write the merged list to, rather than our version.")
+                                                   .takes_value(true)))
+            .subcommand(App::new("log")
+                                .about("Shows the history of changes to your TODO list, if it is kept in git.")
+                                .arg(Arg::with_name("number")
+                                                   .short("n")
+                                                   .long("number")
+                                                   .help("Specifies the maximum number of changes to show.")
+                                                   .takes_value(true)))
+            .subcommand(App::new("sync")
+                                .about("Merges changes to your TODO list from its git remote, and pushes yours."))
+            .subcommand(App::new("completions")
+                                .about("Prints a script which completes commands, along with the names of your tasks, tags and lists, in your shell.")
+                                .arg(Arg::with_name("shell")
+                                                   .required(true)
+                                                   .possible_values(&["bash", "zsh", "fish"])))
+            .subcommand(App::new("config")
+                                .about("Shows or changes the settings in your configuration file.")
+                                .subcommand(App::new("list")
+                                                    .about("Lists every setting, including the defaults of settings which aren't in your configuration file."))
+                                .subcommand(App::new("get")
+                                                    .about("Shows a setting, such as defaults.priority, or every setting in a section, such as defaults.")
+                                                    .arg(Arg::with_name("key")
+                                                                       .required(true)))
+                                .subcommand(App::new("set")
+                                                    .about("Changes a setting in your configuration file, as long as the new value is valid.")
+                                                    .arg(Arg::with_name("key")
+                                                                       .required(true))
+                                                    .arg(Arg::with_name("value")
+                                                                       .required(true))))
+            .subcommand(App::new("lists")
+                                .about("Shows your named lists, marking the one in use."))
+            .subcommand(App::new("list-create")
+                                .about("Creates a new, empty named list, such as one for work and one for home.")
+                                .arg(Arg::with_name("name")
+                                                   .required(true)))
+            .subcommand(App::new("use")
+                                .about("Chooses the named list that commands use from now on.")
+                                .arg(Arg::with_name("name")
+                                                   .required(true)))
+            .subcommand(App::new("move")
+                                .about("Moves a task and its subtasks to another of your named lists, keeping their IDs.")
+                                .arg(Arg::with_name("task_name")
+                                                   .required(true))
+                                .arg(Arg::with_name("target_list")
+                                                   .required(true))
+                                .arg(Arg::with_name("under")
+                                                   .short("u")
+                                                   .long("under")
+                                                   .help("Adds the task as a subtask of the given task in the other list.")
+                                                   .takes_value(true)))
             .subcommand(App::new("remove")
                                 .about("Removes something from your TODO list.")
+                                .setting(AppSettings::ArgsNegateSubcommands)
+                                .arg(Arg::with_name("filter")
+                                                   .short("f")
+                                                   .long("filter")
+                                                   .help("Removes every task matching a filter expression, such as \"status:complete\", along with its subtasks.")
+                                                   .takes_value(true))
+                                .arg(Arg::with_name("yes")
+                                                   .short("y")
+                                                   .long("yes")
+                                                   .help("Goes ahead without asking, however many tasks match the filter."))
                                 .subcommand(App::new("task")
                                                     .about("Removes a task from your TODO list.")
                                                     .arg(Arg::with_name("task_name")
@@ -452,50 +1355,363 @@ fn cli() -> ArgMatches<'static> {
                                                     .arg(Arg::with_name("tag_name")
                                                                        .required(true)
                                                                        .multiple(true))))
-            .get_matches()
+}
+
+fn merge_files(app: &ArgMatches) -> Result<(), String> {
+    // Merges TODO lists given as files, leaving the TODO list in ~/.config/faros alone, as git may be running this as a merge driver in the middle of changing it.
+    let read = |name: &str| TODOList::read_from(app.value_of(name).unwrap());
+    let (merged, conflicts) = merge::merge(&read("base")?, &read("ours")?, &read("theirs")?);
+    merged.write_to(app.value_of("output").or_else(|| app.value_of("ours")).unwrap())?;
+    // Exiting with an error when there are conflicts lets git know that the merge needs to be looked at.
+    if conflicts.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("The TODO lists were merged, but some changes conflicted:{}", conflicts.iter().map(|conflict| format!("\n    {}", conflict)).collect::<String>()))
+    }
+}
+
+fn configure(app: &ArgMatches) -> Result<(), String> {
+    // Shows and changes settings. This is done before the configuration is read, so that a configuration file which has become invalid can still be fixed with faros config set.
+    let path = config::path().ok_or_else(|| String::from("There is nowhere to keep your configuration, as $HOME is not set."))?;
+    let contents = if path.exists() {
+        fs::read_to_string(&path).map_err(|_| format!("{} exists, but couldn't be read.", path.display()))?
+    } else {
+        String::new()
+    };
+    let show = |value: &toml::Value| match value {
+        toml::Value::String(text) => text.clone(),
+        value => value.to_string()
+    };
+
+    match app.subcommand() {
+        ("set", Some(subapp)) => {
+            let key = subapp.value_of("key").unwrap();
+            let contents = config::set(&contents, key, subapp.value_of("value").unwrap())
+                .map_err(|err| format!("{} was left as it was, as the new value is invalid: {}", path.display(), err))?;
+            path.parent().map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(&path, contents))
+                .map_err(|_| format!("{} could not be written to.", path.display()))?;
+        },
+        (command, subapp) => {
+            let config = Config::parse(&contents).map_err(|err| format!("{} is invalid: {}", path.display(), err))?;
+            let mut settings = config.settings();
+            if let ("get", Some(subapp)) = (command, subapp) {
+                let key = subapp.value_of("key").unwrap();
+                if let Some((_, value)) = settings.iter().find(|(k, _)| k == key) {
+                    println!("{}", show(value));
+                    return Ok(());
+                }
+                settings.retain(|(k, _)| k.starts_with(&format!("{}.", key)));
+                if settings.is_empty() {
+                    return Err(format!("There is no setting named {}. See `faros config list`.", key));
+                }
+            }
+            for (key, value) in settings {
+                println!("{} = {}", key, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_completions(shell: &str) {
+    let mut generated = Vec::new();
+    app().gen_completions_to("faros", shell.parse::<Shell>().unwrap(), &mut generated);
+    print!("{}", completion::script(shell, &String::from_utf8_lossy(&generated)));
+}
+
+fn complete_words(args: &[String]) {
+    // Prints what could be given in place of one word of a command line, for the scripts printed by `faros completions`, which pass the position of the word followed by the whole command line.
+    let index = args.first().and_then(|i| i.parse::<usize>().ok()).unwrap_or(0);
+    for candidate in completions_for(index, args.get(1..).unwrap_or_default().to_vec(), None) {
+        println!("{}", candidate);
+    }
+}
+
+fn completions_for(index: usize, words: Vec<String>, todo_list: Option<&TODOList>) -> Vec<String> {
+    // Finds what could be given in place of the word at the given index of a command line, looking tasks up in the given TODO list, or otherwise in the list the command line names. The command line is parsed with a marker in place of the word, so that the argument the marker becomes tells what is expected there. Required arguments after it are filled in with placeholders, so that the command line can be parsed.
+    const MARKER: &str = "\u{1}complete";
+    const FILLER: &str = "\u{1}fill";
+    let mut words = words;
+    if index == 0 || index > words.len() {
+        return Vec::new();
+    }
+    let current = completion::unquote(words.get(index).map_or("", String::as_str));
+    if current.starts_with('-') {
+        return Vec::new();
+    }
+    words.truncate(index);
+    words.push(String::from(MARKER));
+    let mut words = match alias::expand(words, &config::aliases()) {
+        Ok(words) => words,
+        Err(_) => return Vec::new()
+    };
+    let matches = loop {
+        match app().get_matches_from_safe(&words) {
+            Ok(matches) => break matches,
+            Err(err) if err.kind == ErrorKind::MissingRequiredArgument && words.len() < index + 8 => words.push(String::from(FILLER)),
+            Err(_) => return Vec::new()
+        }
+    };
+
+    let mut commands = Vec::new();
+    let mut current_matches = &matches;
+    let (arg, app) = loop {
+        let found = ["task_name", "parent_name", "root", "under", "tag", "tags", "tag_name", "add_tag", "remove_tag", "list_name", "target_list"].iter().find(|name| {
+            current_matches.values_of(name).is_some_and(|mut values| values.any(|value| value == MARKER))
+        });
+        if let Some(arg) = found {
+            break (*arg, current_matches);
+        }
+        match current_matches.subcommand() {
+            (name, Some(subcommand)) => {
+                commands.push(name);
+                current_matches = subcommand;
+            },
+            _ => return Vec::new()
+        }
+    };
+    let kind = match arg {
+        "tag" | "tags" | "tag_name" | "add_tag" | "remove_tag" => completion::Kind::Tag,
+        "list_name" | "target_list" => completion::Kind::List,
+        _ => completion::Kind::Task { incomplete_only: commands == ["complete"] }
+    };
+
+    // The task given with --under when moving a task is in the list it is being moved to.
+    let list_file = match app.value_of("target_list").filter(|list| *list != FILLER) {
+        Some(target) if arg == "under" => match data_dir() {
+            Some(dir) if lists::validate(target).is_ok() && lists::exists(&dir, target) => Some(lists::path(&dir, target)),
+            _ => return Vec::new()
+        },
+        _ if todo_list.is_some() => None,
+        _ => match list_path(global_value(&matches, "list_file"), global_value(&matches, "list_name")) {
+            Ok(list_file) => Some(list_file),
+            Err(_) => return Vec::new()
+        }
+    };
+    let empty = TODOList { tasks: Vec::new(), tags: Vec::new() };
+    let read;
+    let (todo_list, lists) = match (&kind, list_file) {
+        (completion::Kind::List, _) => (&empty, data_dir().map(|dir| lists::names(&dir)).unwrap_or_default()),
+        (_, None) => (todo_list.unwrap(), Vec::new()),
+        (_, Some(list_file)) => match list_file.to_str().map(TODOList::read_from) {
+            Some(Ok(todo_list)) => {
+                read = todo_list;
+                (&read, Vec::new())
+            },
+            _ => return Vec::new()
+        }
+    };
+    completion::candidates(todo_list, &kind, lists, &current)
+}
+
+fn change_message(matches: &ArgMatches) -> String {
+    // Describes a command for the history of the TODO list, such as "complete: Write report", from the names of its subcommands and what it was given.
+    let mut words = Vec::new();
+    let mut current = matches;
+    while let (name, Some(subcommand)) = current.subcommand() {
+        words.push(name);
+        current = subcommand;
+    }
+    let subject = ["task_name", "name", "parent_name", "tag_name", "file", "filter"].iter()
+                                                                                      .find_map(|arg| current.values_of(arg))
+                                                                                      .map(|values| values.collect::<Vec<_>>().join(", "));
+    match subject {
+        Some(subject) => format!("{}: {}", words.join(" "), subject),
+        None => words.join(" ")
+    }
 }
 
 fn main() {
-    let matches = cli();
-    let mut todo_list = TODOList::read();
+    // The completion scripts call back into faros to complete the names of tasks, with a command which is left out of the command line interface.
+    let args = env::args_os().map(|arg| arg.to_string_lossy().into_owned()).collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) == Some("__complete") {
+        complete_words(&args[2..]);
+        return;
+    }
+    if let Err(err) = cli().and_then(|matches| start(&matches)) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn start(matches: &ArgMatches) -> Result<(), String> {
+    // Reads the configuration and the TODO list, then runs the command line, or the shell or batch it starts. Commands which don't use the TODO list are run before either is read.
+    match matches.subcommand() {
+        ("merge", Some(app)) => return merge_files(app),
+        ("completions", Some(app)) => {
+            print_completions(app.value_of("shell").unwrap());
+            return Ok(());
+        },
+        ("config", Some(app)) => return configure(app),
+        _ => ()
+    }
+    let config = Config::read()?;
+    let list_file = list_path(global_value(matches, "list_file"), global_value(matches, "list_name"))?;
+    let mut todo_list = TODOList::read(&list_file)?;
+    if let ("shell", Some(_)) = matches.subcommand() {
+        return shell::run(&config, &list_file, todo_list);
+    }
+    if let ("batch", Some(app)) = matches.subcommand() {
+        return shell::batch(&config, &list_file, todo_list, &read_import(app.value_of("file").unwrap_or("-"))?);
+    }
+    // Commands which change other lists as well as this one, such as moving a task to another list, add them here so that they are committed together.
+    let mut changed = vec![list_file.clone()];
+    run(matches, &config, &list_file, &mut todo_list, &mut changed, None)?;
+    save(&config, &todo_list, &changed, &change_message(matches))
+}
+
+fn save(config: &Config, todo_list: &TODOList, changed: &[path::PathBuf], message: &str) -> Result<(), String> {
+    // Writes the TODO list, which is the first of the changed lists, and commits every changed list to its history.
+    todo_list.write(&changed[0])?;
+    if config.storage.backend == Backend::Git {
+        let changed = changed.iter().map(|list| list.as_path()).collect::<Vec<_>>();
+        history::commit(&config.storage, &changed, message).map_err(|err| format!("Your TODO list was saved, but the change couldn't be committed: {}", err))?;
+    }
+    Ok(())
+}
+
+fn run(matches: &ArgMatches, config: &Config, list_file: &path::Path, todo_list: &mut TODOList, changed: &mut Vec<path::PathBuf>, current: Option<Uuid>) -> Result<(), String> {
+    // Runs a single command against the TODO list, which is saved afterwards by the caller. In the shell, current is the task chosen with cd, which new subtasks are added to by default.
+    let format = global_value(matches, "format")
+                     .map(|f| f.parse::<Format>().map_err(|err| format!("Error: {}", err)))
+                     .transpose()?;
 
     match matches.subcommand() {
         ("list", Some(app)) => {
-            let max_days = app.value_of("days")
-                              .map_or_else(|| 3, 
-                                           |d| {
-                                               d.parse::<i64>().unwrap_or_else(|_| {
-                                                   eprintln!("Error: Unexpected value, expected [int]. found\"{}\".", d);
-                                                   std::process::exit(1);
-                                               })
-                                           });
-            let max_number = app.value_of("number")
-                                         .map_or_else(|| 1000000000,
-                                                      |n| {
-                                                          n.parse::<usize>().unwrap_or_else(|_| {
-                                                              eprintln!("Error: Unexpected value, expected [int]. found\"{}\".", n);
-                                                               std::process::exit(1);
-                                                          })
-                                                      });
-            let high = app.is_present("high");
-            let medium = app.is_present("medium");
-            let low = app.is_present("low");
-            let _tags = app.values_of("tag").and_then(|t| Some(t.collect::<Vec<_>>()));
-
-            let mut tasks = todo_list.flattened();
-            tasks.sort_by(|task1, task2| task1.due_date.cmp(&task2.due_date));
-            for task in tasks.iter().filter(|t| t.valid(high, medium, low, max_days)).take(max_number) {
-                println!("{}", task);
+            let mut view = config.default_view();
+            let mut filter = Filter::parse(&view.filter).unwrap();
+            if let Some(d) = app.value_of("days") {
+                filter.max_days = Some(d.parse::<i64>().map_err(|_| format!("Error: Unexpected value, expected [int]. found\"{}\".", d))?);
+            }
+            if let Some(n) = app.value_of("number") {
+                view.limit = Some(n.parse::<usize>().map_err(|_| format!("Error: Unexpected value, expected [int]. found\"{}\".", n))?);
+            }
+            if app.is_present("high") || app.is_present("medium") || app.is_present("low") {
+                filter.priorities.clear();
+                if app.is_present("high") {
+                    filter.priorities.push(Priority::High);
+                }
+                if app.is_present("medium") {
+                    filter.priorities.push(Priority::Medium);
+                }
+                if app.is_present("low") {
+                    filter.priorities.push(Priority::Low);
+                }
+            }
+            if let Some(tags) = app.values_of("tag") {
+                filter.tags.extend(tags.map(String::from));
+            }
+            if let Some(c) = app.value_of("columns") {
+                view.columns = c.split(',').map(|column| column.trim().parse()).collect::<Result<_, _>>().map_err(|err| format!("Error: {}", err))?;
+            }
+            if app.is_present("long") {
+                view.format = Format::Long;
+            }
+            if let Some(f) = format {
+                view.format = f;
+            }
+
+            if app.is_present("all_lists") {
+                list_all(&view, &filter, app.is_present("tree"))?;
+            } else {
+                list(todo_list, &view, &filter, app.is_present("tree"))?;
             }
         },
+        ("view", Some(app)) => {
+            let view_name = app.value_of("view_name").unwrap();
+            let mut view = config.view(view_name).ok_or_else(|| format!("There is no view named {}.", view_name))?;
+            if let Some(f) = format {
+                view.format = f;
+            }
+
+            list(todo_list, &view, &Filter::parse(&view.filter).unwrap(), false)?;
+        },
+        ("tree", Some(app)) => {
+            let depth = app.value_of("depth").map(|d| {
+                d.parse::<usize>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", d))
+            }).transpose()?;
+            let options = TreeOptions {
+                depth,
+                collapse_completed: app.is_present("collapse")
+            };
+
+            match app.value_of("root") {
+                Some(root_name) => {
+                    let root = todo_list.task_from_name(root_name)?.ok_or_else(|| format!("There is no task named {}", root_name))?;
+                    let uuid = root.uuid;
+                    let root = todo_list.path(uuid).pop().unwrap();
+                    print_tree(&[root], todo_list, &|_| true, &options, format.unwrap_or(Format::Table))?;
+                },
+                None => {
+                    let roots = todo_list.tasks.iter().collect::<Vec<_>>();
+                    print_tree(&roots, todo_list, &|_| true, &options, format.unwrap_or(Format::Table))?;
+                }
+            }
+        },
+        ("tui", Some(_)) => {
+            if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+                return Err(String::from("faros tui needs to be run in a terminal."));
+            }
+            *todo_list = tui::run(todo_list.clone(), list_file)?;
+        },
+        ("show", Some(app)) => {
+            let task_name = app.value_of("task_name").unwrap();
+            let uuid = todo_list.task_from_name(task_name)?.ok_or_else(|| format!("There is no task named {}", task_name))?.uuid;
+            let task = todo_list.path(uuid).pop().unwrap();
+
+            match format {
+                Some(f) if f.machine_readable() => {
+                    output::print_records(&[TaskRecord::tree(task, todo_list, &|_| true)], f)?;
+                },
+                _ => show(task, todo_list)
+            }
+        },
+        ("search", Some(app)) => {
+            let query = app.value_of("query").unwrap();
+            let matcher = if app.is_present("regex") {
+                Matcher::regex(query).map_err(|err| format!("Error: {}", err))?
+            } else if app.is_present("fuzzy") {
+                Matcher::fuzzy(query)
+            } else {
+                Matcher::substring(query)
+            };
+            let mut hits = search::search(todo_list, &matcher);
+            if let Some(n) = app.value_of("number") {
+                hits.truncate(n.parse::<usize>().map_err(|_| format!("Error: Unexpected value, expected [int]. found\"{}\".", n))?);
+            }
+
+            match format {
+                Some(f) if f.machine_readable() => {
+                    let records = hits.iter().map(|hit| TaskRecord::new(hit.task, todo_list)).collect::<Vec<_>>();
+                    output::print_records(&records, f)?;
+                },
+                _ => {
+                    let color = render::use_color();
+                    for hit in hits {
+                        let name = hit.fields
+                                      .iter()
+                                      .find(|m| m.field == "name")
+                                      .map_or_else(|| hit.task.name.clone(), |m| search::highlight(&m.text, &m.ranges, color));
+                        println!("{}  {}", hit.task.short_id(), name);
+                        for m in hit.fields.iter().filter(|m| m.field != "name") {
+                            println!("          {}: {}", m.field, search::highlight(&m.text, &m.ranges, color));
+                        }
+                    }
+                }
+            }
+        },
+        ("complete", Some(app)) if app.is_present("filter") => {
+            complete_matching(todo_list, app.value_of("filter").unwrap(), app.is_present("yes"))?;
+        },
         ("complete", Some(app)) => {
             let task_names = app.values_of("task_name").unwrap().collect::<Vec<_>>();
             for name in task_names {
-                match todo_list.task_from_name(name) {
-                    Some(task) => task.complete(),
-                    None => {
-                        eprintln!("No task with name {}.", name);
-                    }
+                match todo_list.task_from_name(name)? {
+                    Some(task) => task.complete()?,
+                    // A batch is applied all at once or not at all, so a task which can't be found fails it, rather than being skipped.
+                    None if IN_BATCH.load(Ordering::Relaxed) => return Err(format!("No task with name {}.", name)),
+                    None => eprintln!("No task with name {}.", name)
                 }
             }
         },
@@ -503,243 +1719,132 @@ fn main() {
             match app.subcommand() {
                 ("task", Some(subapp)) => {
                     let name = subapp.value_of("name")
-                                     .map_or_else(|| {
-                                         print!("Please give your new task a name: ");
-                                         io::stdout().flush();
-                                         let mut buffer = String::new();
-                                         io::stdin().read_line(&mut buffer);
-                                         String::from(buffer.trim())
-                                     },
-                                                  |s| String::from(s));
+                                     .map_or_else(|| prompt_for("task", "name"), |value| Ok(String::from(value)))?;
                     let description = subapp.value_of("description")
-                                            .map_or_else(|| {
-                                                print!("Please give your new task a description: ");
-                                                io::stdout().flush();
-                                                let mut buffer = String::new();
-                                                io::stdin().read_line(&mut buffer);
-                                                String::from(buffer.trim())
-                                            }, 
-                                                         |s| String::from(s));
-                    let year = app.value_of("year")
-                                  .map_or_else(|| Local::now().year(),
-                                               |y| y.parse::<i32>().unwrap_or_else(|_| {
-                                                   eprintln!("Error: Unexpected value, expected [int], found \"{}\".", y);
-                                                   std::process::exit(1);
-                                               }));
-                    let month = app.value_of("month")
-                                   .map_or_else(|| Local::now().month(),
-                                                |m| m.parse::<u32>().unwrap_or_else(|_| {
-                                                    eprintln!("Error: Unexpected value, expected [int], found \"{}\".", m);
-                                                    std::process::exit(1);
-                                                }));
-                    let day = app.value_of("day")
-                                 .map_or_else(|| Local::now().day(),
-                                              |d| d.parse::<u32>().unwrap_or_else(|_| {
-                                                  eprintln!("Error: Unexpected value, expected [int], found \"{}\".", d);
-                                                  std::process::exit(1);
-                                              }));
-                    let hour = app.value_of("hour")
-                                  .map_or_else(|| 23,
-                                               |h| h.parse::<u32>().unwrap_or_else(|_| {
-                                                   eprintln!("Error: Unexpected value, expected [int], found \"{}\".", h);
-                                                   std::process::exit(1);
-                                               }));
-                    let minute = app.value_of("minute")
-                                    .map_or_else(|| 59,
-                                                 |m| m.parse::<u32>().unwrap_or_else(|_| {
-                                                     eprintln!("Error: Unexpected value, expected [int], found \"{}\".", m);
-                                                     std::process::exit(1);
-                                                 }));
+                                            .map_or_else(|| prompt_for("task", "description"), |value| Ok(String::from(value)))?;
+                    let year = subapp.value_of("year")
+                                     .map_or_else(|| Ok(Local::now().year()),
+                                                  |y| y.parse::<i32>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", y)))?;
+                    let month = subapp.value_of("month")
+                                      .map_or_else(|| Ok(Local::now().month()),
+                                                   |m| m.parse::<u32>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", m)))?;
+                    let day = subapp.value_of("day")
+                                    .map_or_else(|| Ok(Local::now().day()),
+                                                 |d| d.parse::<u32>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", d)))?;
+                    let hour = subapp.value_of("hour")
+                                     .map_or_else(|| Ok(config::defaults().due_time.hour()),
+                                                  |h| h.parse::<u32>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", h)))?;
+                    let minute = subapp.value_of("minute")
+                                       .map_or_else(|| Ok(config::defaults().due_time.minute()),
+                                                    |m| m.parse::<u32>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", m)))?;
 
                     todo_list.tasks.push(
-                        Task::new(name, description, Priority::Medium, Local.ymd(year, month, day).and_hms(hour, minute, 0)));
+                        Task::new(name, description, config::defaults().priority, due_at(year, month, day, hour, minute)?));
                 },
                 ("subtask", Some(subapp)) => {
-                    let parent_name = subapp.value_of("parent_name").unwrap();
                     let name = subapp.value_of("name")
-                                     .map_or_else(|| {
-                                         print!("Please give your new task a name: ");
-                                         io::stdout().flush();
-                                         let mut buffer = String::new();
-                                         io::stdin().read_line(&mut buffer);
-                                         String::from(buffer.trim())
-                                     },
-                                                  |s| String::from(s));
+                                     .map_or_else(|| prompt_for("task", "name"), |value| Ok(String::from(value)))?;
                     let description = subapp.value_of("description")
-                                            .map_or_else(|| {
-                                                print!("Please give your new task a description: ");
-                                                io::stdout().flush();
-                                                let mut buffer = String::new();
-                                                io::stdin().read_line(&mut buffer);
-                                                String::from(buffer.trim())
-                                            }, 
-                                                         |s| String::from(s));
-                    let year = app.value_of("year")
-                                  .map_or_else(|| Local::now().year(),
-                                               |y| y.parse::<i32>().unwrap_or_else(|_| {
-                                                   eprintln!("Error: Unexpected value, expected [int], found \"{}\".", y);
-                                                   std::process::exit(1);
-                                               }));
-                    let month = app.value_of("month")
-                                   .map_or_else(|| Local::now().month(),
-                                                |m| m.parse::<u32>().unwrap_or_else(|_| {
-                                                    eprintln!("Error: Unexpected value, expected [int], found \"{}\".", m);
-                                                    std::process::exit(1);
-                                                }));
-                    let day = app.value_of("day")
-                                 .map_or_else(|| Local::now().day(),
-                                              |d| d.parse::<u32>().unwrap_or_else(|_| {
-                                                  eprintln!("Error: Unexpected value, expected [int], found \"{}\".", d);
-                                                  std::process::exit(1);
-                                              }));
-                    let hour = app.value_of("hour")
-                                  .map_or_else(|| 23,
-                                               |h| h.parse::<u32>().unwrap_or_else(|_| {
-                                                   eprintln!("Error: Unexpected value, expected [int], found \"{}\".", h);
-                                                   std::process::exit(1);
-                                               }));
-                    let minute = app.value_of("minute")
-                                    .map_or_else(|| 59,
-                                                 |m| m.parse::<u32>().unwrap_or_else(|_| {
-                                                     eprintln!("Error: Unexpected value, expected [int], found \"{}\".", m);
-                                                     std::process::exit(1);
-                                                 }));
-
-                    match &mut todo_list.task_from_name(parent_name).unwrap_or_else(|| {
-                        eprintln!("There is no task with name {} to which a subtask can be added.", parent_name);
-                        std::process::exit(1);
-                    }).completion {
-                        Completion::Complete => {
-                            todo_list.task_from_name(parent_name)
-                                .unwrap()
-                                .completion = Completion::Incomplete(
-                                    vec![Task::new(name, 
-                                        description, 
-                                        Priority::Medium, 
-                                        Local.ymd(year, month, day).and_hms(hour, minute, 0))]);
-                        },
-                        Completion::Incomplete(children) => {
-                            children.push(Task::new(name, 
-                                description, 
-                                Priority::Medium, 
-                                Local.ymd(year, month, day).and_hms(hour, minute, 0)));
-                        }
-                    }
+                                            .map_or_else(|| prompt_for("task", "description"), |value| Ok(String::from(value)))?;
+                    let year = subapp.value_of("year")
+                                     .map_or_else(|| Ok(Local::now().year()),
+                                                  |y| y.parse::<i32>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", y)))?;
+                    let month = subapp.value_of("month")
+                                      .map_or_else(|| Ok(Local::now().month()),
+                                                   |m| m.parse::<u32>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", m)))?;
+                    let day = subapp.value_of("day")
+                                    .map_or_else(|| Ok(Local::now().day()),
+                                                 |d| d.parse::<u32>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", d)))?;
+                    let hour = subapp.value_of("hour")
+                                     .map_or_else(|| Ok(config::defaults().due_time.hour()),
+                                                  |h| h.parse::<u32>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", h)))?;
+                    let minute = subapp.value_of("minute")
+                                       .map_or_else(|| Ok(config::defaults().due_time.minute()),
+                                                    |m| m.parse::<u32>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", m)))?;
+
+                    let parent = match subapp.value_of("parent_name") {
+                        Some(parent_name) => todo_list.task_from_name(parent_name)?.ok_or_else(|| format!("There is no task with name {} to which a subtask can be added.", parent_name))?.uuid,
+                        None => current.ok_or_else(|| String::from("Please give the name of the task to add a subtask to."))?
+                    };
+                    let due_date = due_at(year, month, day, hour, minute)?;
+                    todo_list.task_from_uuid(parent).add_subtask(Task::new(name,
+                        description,
+                        config::defaults().priority,
+                        due_date));
                 },
                 ("tag", Some(subapp)) => {
-                    let _name = subapp.value_of("name")
-                                     .map_or_else(|| {
-                                         print!("Please give your new tag a name: ");
-                                         io::stdout().flush();
-                                         let mut buffer = String::new();
-                                         io::stdin().read_line(&mut buffer);
-                                         String::from(buffer.trim())
-                                     },
-                                                  |s| String::from(s));
-                    let _description = subapp.value_of("description")
-                                            .map_or_else(|| {
-                                                print!("Please give your new tag a description: ");
-                                                io::stdout().flush();
-                                                let mut buffer = String::new();
-                                                io::stdin().read_line(&mut buffer);
-                                                String::from(buffer.trim())
-                                            },
-                                                         |s| String::from(s));
-                    //TODO: Adding tags and managing them will probably be a future version thing.
+                    let name = subapp.value_of("name")
+                                     .map_or_else(|| prompt_for("tag", "name"), |value| Ok(String::from(value)))?;
+                    let description = subapp.value_of("description")
+                                            .map_or_else(|| prompt_for("tag", "description"), |value| Ok(String::from(value)))?;
+
+                    if todo_list.tags.iter().any(|tag| tag.name == name) {
+                        return Err(format!("There is already a tag named {}.", name));
+                    }
+                    todo_list.tags.push(Tag::new(name, description));
                 },
                 _ => ()
             }
         },
+        ("edit", Some(app)) => {
+            let task_name = app.value_of("task_name").unwrap();
+            let uuid = todo_list.task_from_name(task_name)?.ok_or_else(|| format!("There is no task named {}", task_name))?.uuid;
+            let task = todo_list.path(uuid).pop().unwrap();
+            let fields = edit::edit(task, todo_list)?;
+            match fields {
+                Some(fields) => edit::apply(fields, uuid, todo_list),
+                None => println!("The file was emptied, so the task was left as it was.")
+            }
+        },
+        ("edit-tree", Some(app)) => {
+            let root = app.value_of("task_name").map(|task_name| todo_list.task_from_name(task_name)?.ok_or_else(|| format!("There is no task named {}", task_name)).map(|task| task.uuid)).transpose()?;
+            let changes = edit::edit_tree(todo_list, root)?;
+            match changes {
+                Some(changes) => {
+                    for problem in &changes.problems {
+                        eprintln!("{}", problem);
+                    }
+                    println!("Kept {}, added {} and removed {} task(s).", changes.kept, changes.added, changes.removed);
+                    *todo_list = changes.todo_list;
+                },
+                None => println!("The file was emptied, so your tasks were left as they were.")
+            }
+        },
+        ("modify", Some(app)) if app.is_present("filter") => modify_matching(todo_list, app)?,
         ("modify", Some(app)) => {
             match app.subcommand() {
                 ("task", Some(subapp)) => {
                     let task_name = subapp.value_of("task_name").unwrap();
                     let name = subapp.value_of("name");
                     let year = subapp.value_of("year").map(|y| {
-                        y.parse::<i32>().unwrap_or_else(|_| {
-                            eprintln!("Error: Unexpected value, expected [int], found \"{}\".", y);
-                            std::process::exit(1);
-                        })
-                    });
+                        y.parse::<i32>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", y))
+                    }).transpose()?;
                     let month = subapp.value_of("month").map(|m| {
-                        m.parse::<u32>().unwrap_or_else(|_| {
-                            eprintln!("Error: Unexpected value, expected [int], found \"{}\".", m);
-                            std::process::exit(1);
-                        })
-                    });
+                        m.parse::<u32>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", m))
+                    }).transpose()?;
                     let day = subapp.value_of("day").map(|d| {
-                        d.parse::<u32>().unwrap_or_else(|_| {
-                            eprintln!("Error: Unexpected value, expected [int], found \"{}\".", d);
-                            std::process::exit(1);
-                        })
-                    });
+                        d.parse::<u32>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", d))
+                    }).transpose()?;
                     let hour = subapp.value_of("hour").map(|h| {
-                        h.parse::<u32>().unwrap_or_else(|_| {
-                            eprintln!("Error: Unexpected value, expected [int], found \"{}\".", h);
-                            std::process::exit(1);
-                        })
-                    });
+                        h.parse::<u32>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", h))
+                    }).transpose()?;
                     let minute = subapp.value_of("minute").map(|m| {
-                        m.parse::<u32>().unwrap_or_else(|_| {
-                            eprintln!("Error: Unexpected value, expected [int], found \"{}\".", m);
-                            std::process::exit(1);
-                        })
-                    });
-                    let _tags = subapp.values_of("tag").and_then(|t| Some(t.collect::<Vec<_>>()));
-
-                    let task = todo_list.task_from_name(task_name).unwrap_or_else(|| {
-                        eprintln!("There is no task named {}", task_name);
-                        std::process::exit(1);
-                    });
+                        m.parse::<u32>().map_err(|_| format!("Error: Unexpected value, expected [int], found \"{}\".", m))
+                    }).transpose()?;
+                    let _tags = subapp.values_of("tag").map(|t| t.collect::<Vec<_>>());
+
+                    let task = todo_list.task_from_name(task_name)?.ok_or_else(|| format!("There is no task named {}", task_name))?;
 
+                    if year.is_some() || month.is_some() || day.is_some() || hour.is_some() || minute.is_some() {
+                        // The parts of the due date which aren't given are kept, except that a day which the new month doesn't have becomes its last day, so that a task due on the 31st can be moved to a shorter month.
+                        let old = task.due_date;
+                        let (year, month) = (year.unwrap_or(old.year()), month.unwrap_or(old.month()));
+                        let day = day.unwrap_or_else(|| (28..=old.day()).rev().find(|d| NaiveDate::from_ymd_opt(year, month, *d).is_some()).unwrap_or(old.day()));
+                        task.due_date = due_at(year, month, day, hour.unwrap_or(old.hour()), minute.unwrap_or(old.minute()))?;
+                    }
                     if let Some(n) = name {
                         task.name = String::from(n);
                     }
-                    if let Some(y) = year {
-                        task.due_date = Local.ymd(y, 
-                            task.due_date.month(), 
-                            task.due_date.day())
-                            .and_hms(
-                                task.due_date.hour(), 
-                                task.due_date.minute(), 
-                                0);
-                    }
-                    if let Some(m) = month {
-                        task.due_date = Local.ymd(task.due_date.year(), 
-                            m, 
-                            task.due_date.day())
-                            .and_hms(
-                                task.due_date.hour(), 
-                                task.due_date.minute(), 
-                                0);
-                    }
-                    if let Some(d) = day {
-                        task.due_date = Local.ymd(task.due_date.year(), 
-                            task.due_date.month(), 
-                            d)
-                            .and_hms(
-                                task.due_date.hour(), 
-                                task.due_date.minute(), 
-                                0);
-                    }
-                    if let Some(h) = hour {
-                        task.due_date = Local.ymd(task.due_date.year(), 
-                            task.due_date.month(), 
-                            task.due_date.day())
-                            .and_hms(
-                                h, 
-                                task.due_date.minute(), 
-                                0);
-                    }
-                    if let Some(m) = minute {
-                        task.due_date = Local.ymd(task.due_date.year(), 
-                            task.due_date.month(), 
-                            task.due_date.day())
-                            .and_hms(
-                                task.due_date.hour(), 
-                                m, 
-                                0);
-                    }
                 },
                 ("tag", Some(subapp)) => {
                     let _tag_name = subapp.value_of("tag_name").unwrap();
@@ -750,20 +1855,215 @@ fn main() {
                 _ => ()
             }
         },
+        ("import", Some(app)) => {
+            match app.subcommand() {
+                ("csv", Some(subapp)) => {
+                    let contents = read_import(subapp.value_of("file").unwrap())?;
+                    let delimiter = match subapp.value_of("delimiter") {
+                        Some("\\t") | Some("tab") => b'\t',
+                        Some(d) if d.len() == 1 => d.as_bytes()[0],
+                        Some(d) => {
+                            return Err(format!("{} is not a valid delimiter, expected a single character.", d));
+                        },
+                        None => b','
+                    };
+                    let under = match subapp.value_of("under") {
+                        Some(name) => Some(todo_list.task_from_name(name)?.ok_or_else(|| format!("There is no task named {}.", name))?.uuid),
+                        None => None
+                    };
+                    let options = csv_import::Options {
+                        mappings: subapp.values_of("map").map_or_else(Vec::new, |m| m.collect()),
+                        delimiter,
+                        date_format: subapp.value_of("date_format"),
+                        under
+                    };
+                    // A dry run imports into a copy of the TODO list, which is shown and then thrown away.
+                    let dry_run = subapp.is_present("dry_run");
+                    let mut preview = todo_list.clone();
+                    let target = if dry_run { &mut preview } else { todo_list };
+                    let roots = csv_import::import(&contents, target, &options).map_err(|errors| {
+                        let mut message = String::from("Nothing was imported, as the file has errors:");
+                        for error in errors {
+                            message.push_str(&format!("\n    {}", error));
+                        }
+                        message
+                    })?;
+                    if dry_run {
+                        let roots = roots.iter().map(|uuid| *preview.path(*uuid).last().unwrap()).collect::<Vec<_>>();
+                        println!("The following tasks would be imported:");
+                        tree::print_tree(&roots, &|_| true, &TreeOptions { depth: None, collapse_completed: false });
+                    }
+                },
+                ("todotxt", Some(subapp)) => {
+                    let contents = read_import(subapp.value_of("file").unwrap())?;
+                    print_problems(&todotxt::import(&contents, todo_list));
+                },
+                ("taskwarrior", Some(subapp)) => {
+                    let contents = read_import(subapp.value_of("file").unwrap())?;
+                    let parent_uda = subapp.value_of("parent_uda").unwrap_or("faros_parent");
+                    print_problems(&taskwarrior::import(&contents, todo_list, parent_uda)?);
+                },
+                ("ical", Some(subapp)) => {
+                    let contents = read_import(subapp.value_of("file").unwrap())?;
+                    print_problems(&ical::import(&contents, todo_list).map_err(|err| format!("The file couldn't be parsed as iCalendar: {}", err))?);
+                },
+                ("markdown", Some(subapp)) => {
+                    let contents = read_import(subapp.value_of("file").unwrap())?;
+                    let (mut tasks, problems) = outline::import_markdown(&contents, todo_list);
+                    todo_list.tasks.append(&mut tasks);
+                    print_problems(&problems);
+                },
+                ("org", Some(subapp)) => {
+                    let contents = read_import(subapp.value_of("file").unwrap())?;
+                    let (mut tasks, problems) = outline::import_org(&contents, todo_list);
+                    todo_list.tasks.append(&mut tasks);
+                    print_problems(&problems);
+                },
+                _ => ()
+            }
+        },
+        ("export", Some(app)) => {
+            match app.subcommand() {
+                ("todotxt", Some(subapp)) => {
+                    write_export(subapp.value_of("output"), &todotxt::export(todo_list))?;
+                },
+                ("taskwarrior", Some(subapp)) => {
+                    write_export(subapp.value_of("output"), &taskwarrior::export(todo_list))?;
+                },
+                ("ical", Some(subapp)) => {
+                    write_export(subapp.value_of("output"), &ical::export(todo_list))?;
+                },
+                (outline_format @ "markdown", Some(subapp)) | (outline_format @ "org", Some(subapp)) => {
+                    let roots = match subapp.value_of("task_name") {
+                        Some(task_name) => {
+                            let uuid = todo_list.task_from_name(task_name)?.ok_or_else(|| format!("There is no task named {}", task_name))?.uuid;
+                            todo_list.path(uuid).pop().into_iter().collect()
+                        },
+                        None => todo_list.tasks.iter().collect::<Vec<_>>()
+                    };
+                    let contents = match outline_format {
+                        "markdown" => outline::export_markdown(&roots, todo_list),
+                        _ => outline::export_org(&roots, todo_list)
+                    };
+                    write_export(subapp.value_of("output"), &contents)?;
+                },
+                _ => ()
+            }
+        },
+        ("caldav", Some(app)) => {
+            if let ("sync", Some(subapp)) = app.subcommand() {
+                let settings = config.caldav.as_ref().ok_or_else(|| String::from("CalDAV isn't set up. Add a [caldav] section with the url of a collection to your configuration file."))?;
+                let policy = subapp.value_of("conflicts").map_or(settings.conflicts, |p| p.parse().unwrap());
+                let server = caldav::Server::new(settings)?;
+                let mut state = caldav::State::read(&settings.url, list_file);
+                let report = caldav::sync(todo_list, &server, &mut state, policy).map_err(|err| format!("The sync failed, and nothing was changed: {}", err))?;
+                state.write(list_file)?;
+                println!("Sent {} and fetched {} task(s), deleted {} here and {} on the server.",
+                         report.pushed, report.pulled, report.deleted_locally, report.deleted_remotely);
+                print_problems(&report.problems);
+                if !report.conflicts.is_empty() {
+                    eprintln!("Some tasks were changed in conflicting ways, and were left alone. Sync with --conflicts local or --conflicts remote to settle them:");
+                    for conflict in &report.conflicts {
+                        eprintln!("    {}", conflict);
+                    }
+                }
+                if let Some(err) = &report.interrupted {
+                    eprintln!("The sync stopped before every change was sent, so sync again to finish it. What was synced so far has been kept: {}", err);
+                }
+            }
+        },
+        ("log", Some(app)) => {
+            if config.storage.backend != Backend::Git {
+                return Err(String::from("Your TODO list has no history. Set backend = \"git\" in the [storage] section of your configuration file to keep one."));
+            }
+            if let Some(n) = app.value_of("number").filter(|n| n.parse::<usize>().is_err()) {
+                return Err(format!("Error: Unexpected value, expected [int], found \"{}\".", n));
+            }
+            print!("{}", history::log(&config.storage, list_file, app.value_of("number"))?);
+        },
+        ("sync", Some(_)) => {
+            if config.storage.backend != Backend::Git {
+                return Err(String::from("Your TODO list isn't kept in git. Set backend = \"git\" in the [storage] section of your configuration file to sync it."));
+            }
+            // The TODO list is read again after syncing, so that the changes fetched from the remote aren't overwritten.
+            let result = history::commit(&config.storage, &[list_file], "sync").and_then(|_| history::sync(&config.storage, list_file));
+            match result {
+                Ok(conflicted) => {
+                    *todo_list = TODOList::read(list_file)?;
+                    if conflicted {
+                        eprintln!("Some of your changes conflicted with changes on the remote, and the remote's were kept. See `faros log`.");
+                    }
+                    println!("Your TODO list is in sync with {}.", config.storage.remote.as_deref().unwrap_or("the remote"));
+                },
+                Err(err) => {
+                    return Err(format!("Your TODO list couldn't be synced, and was left as it was: {}", err));
+                }
+            }
+        },
+        ("lists", Some(_)) => {
+            let dir = lists_dir()?;
+            for name in lists::names(&dir) {
+                let marker = if lists::path(&dir, &name) == list_file { "*" } else { " " };
+                println!("{} {}", marker, name);
+            }
+        },
+        ("list-create", Some(app)) => {
+            let name = app.value_of("name").unwrap();
+            let dir = lists_dir()?;
+            lists::validate(name)?;
+            if lists::exists(&dir, name) {
+                return Err(format!("There is already a list named {}.", name));
+            }
+            let path = lists::path(&dir, name);
+            TODOList::read(&path)?.write(&path)?;
+            changed.push(path);
+            println!("Created the list {}. Switch to it with `faros use {}`, or pass `--list {}` to a single command.", name, name, name);
+        },
+        ("use", Some(app)) => {
+            let name = app.value_of("name").unwrap();
+            named_list(name)?;
+            lists::set_current(&lists_dir()?, name)?;
+            if env::var_os("FAROS_DATA").is_some_and(|f| !f.is_empty()) {
+                eprintln!("Note that $FAROS_DATA is set, and is used rather than the list chosen with `faros use`.");
+            }
+        },
+        ("move", Some(app)) => {
+            let task_name = app.value_of("task_name").unwrap();
+            let target_file = named_list(app.value_of("target_list").unwrap())?;
+            if fs::canonicalize(&target_file).ok() == fs::canonicalize(list_file).ok() {
+                return Err(format!("{} is already in that list.", task_name));
+            }
+            let uuid = todo_list.task_from_name(task_name)?.ok_or_else(|| format!("There is no task named {}", task_name))?.uuid;
+            let mut task = todo_list.path(uuid).pop().unwrap().clone();
+            let mut target = TODOList::read(&target_file)?;
+            // Tasks keep their IDs when they are moved, so a task can't be moved into a list which already has a task with the same ID, such as a copy imported earlier.
+            let existing = target.flattened().iter().map(|t| t.uuid).collect::<Vec<_>>();
+            if let Some(duplicate) = task.flattened().iter().find(|t| existing.contains(&t.uuid)) {
+                return Err(format!("The other list already has a task with the same ID as {}, so nothing was moved.", duplicate.name));
+            }
+            lists::carry_tags(&mut task, &todo_list.tags, &mut target);
+            match app.value_of("under") {
+                Some(parent_name) => target.task_from_name(parent_name)?.ok_or_else(|| format!("There is no task named {} in the other list.", parent_name))?.add_subtask(task),
+                None => target.tasks.push(task)
+            }
+            todo_list.remove_uuid(uuid);
+            target.write(&target_file)?;
+            changed.push(target_file);
+        },
+        ("remove", Some(app)) if app.is_present("filter") => {
+            remove_matching(todo_list, app.value_of("filter").unwrap(), app.is_present("yes"))?;
+        },
         ("remove", Some(app)) => {
             match app.subcommand() {
                 ("task", Some(subapp)) => {
                     let task_names = subapp.values_of("task_name").unwrap().collect::<Vec<_>>();
                     for name in task_names {
-                        let task_uuid = todo_list.task_from_name(name).unwrap_or_else(|| {
-                            eprintln!("There is no task named {}", name);
-                            std::process::exit(1);
-                        }).uuid;
+                        let task_uuid = todo_list.task_from_name(name)?.ok_or_else(|| format!("There is no task named {}", name))?.uuid;
                         todo_list.remove_uuid(task_uuid);
                     }
                 },
                 ("tag", Some(subapp)) => {
-                    let _tag_names = subapp.values_of("task_name").unwrap().collect::<Vec<_>>();
+                    let _tag_names = subapp.values_of("tag_name").unwrap().collect::<Vec<_>>();
                     // Again, to be implemented at the same time as task addition and modification.
                 },
                 _ => ()
@@ -771,6 +2071,5 @@ fn main() {
         },
         _ => ()
     }
-
-    todo_list.write();
+    Ok(())
 }
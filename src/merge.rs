@@ -0,0 +1,342 @@
+use chrono::{DateTime, Local};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+use crate::{Completion, Priority, TODOList, Tag, Task};
+
+#[derive(Clone, PartialEq)]
+struct Entry {
+    name: String,
+    description: String,
+    priority: Priority,
+    due_date: DateTime<Local>,
+    complete: bool,
+    tags: Vec<Uuid>,
+    parent: Option<Uuid>
+}
+
+fn entries(todo_list: &TODOList) -> (Vec<Uuid>, HashMap<Uuid, Entry>) {
+    // Flattens a TODO list into a map from UUIDs to the fields of each task, including its parent, along with the order in which the tasks appear.
+    fn add(task: &Task, parent: Option<Uuid>, order: &mut Vec<Uuid>, entries: &mut HashMap<Uuid, Entry>) {
+        order.push(task.uuid);
+        entries.insert(task.uuid, Entry {
+            name: task.name.clone(),
+            description: task.description.clone(),
+            priority: task.priority,
+            due_date: task.due_date,
+            complete: matches!(task.completion, Completion::Complete),
+            tags: task.tags.clone(),
+            parent
+        });
+        for child in task.children() {
+            add(child, Some(task.uuid), order, entries);
+        }
+    }
+    let mut order = Vec::new();
+    let mut entries = HashMap::new();
+    for task in &todo_list.tasks {
+        add(task, None, &mut order, &mut entries);
+    }
+    (order, entries)
+}
+
+fn merge_field<T: Clone + PartialEq>(field: &str, base: Option<&T>, ours: &T, theirs: &T, describe: &dyn Fn(&T) -> String, task: &str, conflicts: &mut Vec<String>) -> T {
+    // Takes whichever side changed a field, or either side if both made the same change. If both sides changed it differently, our version is kept and the conflict is recorded.
+    if ours == theirs || base == Some(theirs) {
+        ours.clone()
+    } else if base == Some(ours) {
+        theirs.clone()
+    } else {
+        conflicts.push(format!("{}: both sides changed its {} (ours: {}, theirs: {}), so ours was kept", task, field, describe(ours), describe(theirs)));
+        ours.clone()
+    }
+}
+
+fn merge_set(base: &[Uuid], ours: &[Uuid], theirs: &[Uuid]) -> Vec<Uuid> {
+    // Keeps every UUID which either side added, and drops every UUID which either side removed.
+    let mut merged = ours.iter().filter(|uuid| theirs.contains(uuid) || !base.contains(uuid)).copied().collect::<Vec<_>>();
+    merged.extend(theirs.iter().filter(|uuid| !ours.contains(uuid) && !base.contains(uuid)));
+    merged
+}
+
+fn merge_entry(base: Option<&Entry>, ours: &Entry, theirs: &Entry, parent_name: &dyn Fn(&Option<Uuid>) -> String, conflicts: &mut Vec<String>) -> Entry {
+    let task = &ours.name;
+    let quoted = |text: &String| format!("\"{}\"", text);
+    Entry {
+        name: merge_field("name", base.map(|b| &b.name), &ours.name, &theirs.name, &quoted, task, conflicts),
+        description: merge_field("description", base.map(|b| &b.description), &ours.description, &theirs.description, &quoted, task, conflicts),
+        priority: merge_field("priority", base.map(|b| &b.priority), &ours.priority, &theirs.priority, &|p| p.to_string(), task, conflicts),
+        due_date: merge_field("due date", base.map(|b| &b.due_date), &ours.due_date, &theirs.due_date, &|d| d.format("%Y-%m-%d %H:%M").to_string(), task, conflicts),
+        complete: merge_field("status", base.map(|b| &b.complete), &ours.complete, &theirs.complete, &|&c| String::from(if c { "complete" } else { "incomplete" }), task, conflicts),
+        tags: merge_set(base.map_or(&[], |b| &b.tags), &ours.tags, &theirs.tags),
+        parent: merge_field("parent", base.map(|b| &b.parent), &ours.parent, &theirs.parent, parent_name, task, conflicts)
+    }
+}
+
+fn merge_tags(base: &[Tag], ours: &[Tag], theirs: &[Tag], conflicts: &mut Vec<String>) -> Vec<Tag> {
+    // Merges tags by UUID in the same way as tasks, keeping any tag which one side removed and the other changed.
+    let find = |tags: &[Tag], uuid: Uuid| tags.iter().find(|tag| tag.uuid == uuid).cloned();
+    let mut order = ours.iter().map(|tag| tag.uuid).collect::<Vec<_>>();
+    order.extend(theirs.iter().map(|tag| tag.uuid).filter(|uuid| !ours.iter().any(|tag| tag.uuid == *uuid)));
+    let same = |a: &Tag, b: &Tag| a.name == b.name && a.description == b.description;
+    let quoted = |text: &String| format!("\"{}\"", text);
+    let mut merged = Vec::new();
+    for uuid in order {
+        match (find(base, uuid), find(ours, uuid), find(theirs, uuid)) {
+            (base, Some(ours), Some(theirs)) => {
+                let task = format!("tag {}", ours.name);
+                merged.push(Tag {
+                    name: merge_field("name", base.as_ref().map(|b| &b.name), &ours.name, &theirs.name, &quoted, &task, conflicts),
+                    description: merge_field("description", base.as_ref().map(|b| &b.description), &ours.description, &theirs.description, &quoted, &task, conflicts),
+                    uuid
+                });
+            },
+            (Some(base), Some(tag), None) | (Some(base), None, Some(tag)) => {
+                if !same(&base, &tag) {
+                    conflicts.push(format!("tag {}: one side removed it while the other changed it, so it was kept", tag.name));
+                    merged.push(tag);
+                }
+            },
+            (None, Some(tag), None) | (None, None, Some(tag)) => merged.push(tag),
+            (_, None, None) => ()
+        }
+    }
+    merged
+}
+
+pub fn merge(base: &TODOList, ours: &TODOList, theirs: &TODOList) -> (TODOList, Vec<String>) {
+    // Merges two versions of a TODO list which were both changed from a common base, task by task and field by field, matching tasks by UUID. Returns the merged list along with a description of each conflict, in which case our side of the conflict is kept.
+    let mut conflicts = Vec::new();
+    let (_, base_entries) = entries(base);
+    let (mut order, our_entries) = entries(ours);
+    let (their_order, their_entries) = entries(theirs);
+    order.extend(their_order.into_iter().filter(|uuid| !our_entries.contains_key(uuid)));
+
+    let parent_name = |parent: &Option<Uuid>| match parent {
+        Some(uuid) => our_entries.get(uuid).or_else(|| their_entries.get(uuid)).map_or_else(|| uuid.to_string(), |entry| entry.name.clone()),
+        None => String::from("none")
+    };
+    let mut merged: HashMap<Uuid, Entry> = HashMap::new();
+    for uuid in &order {
+        let entry = match (base_entries.get(uuid), our_entries.get(uuid), their_entries.get(uuid)) {
+            (base, Some(ours), Some(theirs)) => merge_entry(base, ours, theirs, &parent_name, &mut conflicts),
+            // A task which one side removed, or removed by completing its parent, stays removed unless the other side changed it.
+            (Some(base), Some(entry), None) | (Some(base), None, Some(entry)) => {
+                if entry == base {
+                    continue;
+                }
+                conflicts.push(format!("{}: one side removed it while the other changed it, so it was kept", entry.name));
+                entry.clone()
+            },
+            (None, Some(entry), None) | (None, None, Some(entry)) => entry.clone(),
+            (_, None, None) => continue
+        };
+        merged.insert(*uuid, entry);
+    }
+    order.retain(|uuid| merged.contains_key(uuid));
+
+    // Tasks whose parents were removed, and tasks which the two sides moved beneath each other, become top-level tasks.
+    for uuid in &order {
+        if merged[uuid].parent.is_some_and(|parent| !merged.contains_key(&parent)) {
+            conflicts.push(format!("{}: its parent was removed, so it was made a top-level task", merged[uuid].name));
+            merged.get_mut(uuid).unwrap().parent = None;
+        }
+    }
+    for uuid in &order {
+        let mut seen = HashSet::new();
+        let mut current = *uuid;
+        while let Some(parent) = merged[&current].parent {
+            if parent == *uuid {
+                conflicts.push(format!("{}: the two sides moved it beneath its own subtasks, so it was made a top-level task", merged[uuid].name));
+                merged.get_mut(uuid).unwrap().parent = None;
+                break;
+            }
+            if !seen.insert(parent) {
+                break;
+            }
+            current = parent;
+        }
+    }
+    // Complete tasks can't have subtasks, so a task which one side completed while the other gave it new or changed subtasks is kept incomplete.
+    let parents = order.iter().filter_map(|uuid| merged[uuid].parent).collect::<HashSet<_>>();
+    for parent in parents {
+        let entry = merged.get_mut(&parent).unwrap();
+        if entry.complete {
+            conflicts.push(format!("{}: one side completed it while the other changed its subtasks, so it was kept incomplete", entry.name));
+            entry.complete = false;
+        }
+    }
+
+    let tags = merge_tags(&base.tags, &ours.tags, &theirs.tags, &mut conflicts);
+    let mut children: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for uuid in &order {
+        if let Some(parent) = merged[uuid].parent {
+            children.entry(parent).or_default().push(*uuid);
+        }
+    }
+    let tasks = order.iter()
+                     .filter(|uuid| merged[uuid].parent.is_none())
+                     .map(|uuid| build(*uuid, &merged, &children, &tags))
+                     .collect();
+    (TODOList { tasks, tags }, conflicts)
+}
+
+fn build(uuid: Uuid, entries: &HashMap<Uuid, Entry>, children: &HashMap<Uuid, Vec<Uuid>>, tags: &[Tag]) -> Task {
+    let entry = &entries[&uuid];
+    let mut task = Task::new(entry.name.clone(), entry.description.clone(), entry.priority, entry.due_date);
+    task.uuid = uuid;
+    task.tags = entry.tags.iter().copied().filter(|tag| tags.iter().any(|t| t.uuid == *tag)).collect();
+    task.completion = if entry.complete {
+        Completion::Complete
+    } else {
+        Completion::Incomplete(children.get(&uuid)
+                                       .iter()
+                                       .flat_map(|c| c.iter())
+                                       .map(|child| build(*child, entries, children, tags))
+                                       .collect())
+    };
+    task
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn task(name: &str) -> Task {
+        Task::new(String::from(name), String::new(), Priority::Medium, Local.ymd(2030, 1, 1).and_hms(17, 0, 0))
+    }
+
+    fn list(tasks: Vec<Task>) -> TODOList {
+        TODOList { tasks, tags: Vec::new() }
+    }
+
+    fn parent(todo_list: &TODOList, uuid: Uuid) -> Option<Uuid> {
+        let path = todo_list.path(uuid);
+        path.len().checked_sub(2).map(|i| path[i].uuid)
+    }
+
+    #[test]
+    fn a_rename_and_a_completion() {
+        let base = list(vec![task("Draft")]);
+        let mut ours = base.clone();
+        ours.tasks[0].name = String::from("Draft the report");
+        let mut theirs = base.clone();
+        theirs.tasks[0].completion = Completion::Complete;
+
+        let (merged, conflicts) = merge(&base, &ours, &theirs);
+        assert!(conflicts.is_empty(), "{:?}", conflicts);
+        assert_eq!(merged.tasks.len(), 1);
+        assert_eq!(merged.tasks[0].name, "Draft the report");
+        assert_eq!(merged.tasks[0].uuid, base.tasks[0].uuid);
+        assert!(matches!(merged.tasks[0].completion, Completion::Complete));
+    }
+
+    #[test]
+    fn both_sides_changing_a_field() {
+        let base = list(vec![task("Draft")]);
+        let mut ours = base.clone();
+        ours.tasks[0].name = String::from("Ours");
+        ours.tasks[0].priority = Priority::High;
+        let mut theirs = base.clone();
+        theirs.tasks[0].name = String::from("Theirs");
+        theirs.tasks[0].priority = Priority::High;
+
+        let (merged, conflicts) = merge(&base, &ours, &theirs);
+        assert_eq!(conflicts, ["Ours: both sides changed its name (ours: \"Ours\", theirs: \"Theirs\"), so ours was kept"]);
+        assert_eq!(merged.tasks[0].name, "Ours");
+        assert!(merged.tasks[0].priority == Priority::High);
+    }
+
+    #[test]
+    fn a_removal_and_a_change() {
+        let base = list(vec![task("Changed"), task("Untouched")]);
+        let ours = list(Vec::new());
+        let mut theirs = base.clone();
+        theirs.tasks[0].priority = Priority::High;
+
+        let (merged, conflicts) = merge(&base, &ours, &theirs);
+        assert_eq!(conflicts, ["Changed: one side removed it while the other changed it, so it was kept"]);
+        assert_eq!(merged.tasks.len(), 1);
+        assert_eq!(merged.tasks[0].uuid, base.tasks[0].uuid);
+        assert!(merged.tasks[0].priority == Priority::High);
+
+        // The same holds whichever side removed it.
+        let (merged, conflicts) = merge(&base, &theirs, &ours);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(merged.tasks.len(), 1);
+    }
+
+    #[test]
+    fn a_removed_parent() {
+        let mut parent = task("Parent");
+        parent.add_subtask(task("Child"));
+        let base = list(vec![parent]);
+        let child = base.tasks[0].children()[0].uuid;
+        let ours = list(Vec::new());
+        let mut theirs = base.clone();
+        theirs.tasks[0].add_subtask(task("New child"));
+
+        let (merged, conflicts) = merge(&base, &ours, &theirs);
+        assert_eq!(conflicts, ["New child: its parent was removed, so it was made a top-level task"]);
+        assert_eq!(merged.tasks.len(), 1);
+        assert_eq!(merged.tasks[0].name, "New child");
+        assert!(merged.flattened().iter().all(|task| task.uuid != child));
+    }
+
+    #[test]
+    fn moves_which_would_make_a_cycle() {
+        let base = list(vec![task("A"), task("B")]);
+        let (a, b) = (base.tasks[0].uuid, base.tasks[1].uuid);
+        let mut ours = base.clone();
+        let moved = ours.tasks.remove(1);
+        ours.tasks[0].add_subtask(moved);
+        let mut theirs = base.clone();
+        let moved = theirs.tasks.remove(0);
+        theirs.tasks[0].add_subtask(moved);
+
+        let (merged, conflicts) = merge(&base, &ours, &theirs);
+        assert_eq!(conflicts, ["A: the two sides moved it beneath its own subtasks, so it was made a top-level task"]);
+        assert_eq!(merged.tasks.len(), 1);
+        assert_eq!(merged.flattened().len(), 2);
+        assert_eq!(parent(&merged, a), None);
+        assert_eq!(parent(&merged, b), Some(a));
+    }
+
+    #[test]
+    fn completing_a_parent_while_its_subtasks_change() {
+        let mut parent = task("Parent");
+        parent.add_subtask(task("Child"));
+        let base = list(vec![parent]);
+        let mut ours = base.clone();
+        ours.tasks[0].completion = Completion::Complete;
+        let mut theirs = base.clone();
+        theirs.tasks[0].add_subtask(task("Another"));
+
+        let (merged, conflicts) = merge(&base, &ours, &theirs);
+        assert_eq!(conflicts, ["Parent: one side completed it while the other changed its subtasks, so it was kept incomplete"]);
+        // Completing the parent took the subtask which the other side left alone with it, while the new subtask is kept.
+        assert_eq!(merged.tasks[0].children().iter().map(|task| task.name.as_str()).collect::<Vec<_>>(), ["Another"]);
+    }
+
+    #[test]
+    fn tags_and_new_tasks() {
+        let mut base = list(vec![task("Shared")]);
+        let home = base.tag_uuid("home");
+        let work = base.tag_uuid("work");
+        base.tasks[0].tags = vec![home, work];
+        let mut ours = base.clone();
+        ours.tasks[0].tags = vec![home];
+        ours.tasks.push(task("Ours"));
+        let mut theirs = base.clone();
+        let garden = theirs.tag_uuid("garden");
+        theirs.tasks[0].tags = vec![home, work, garden];
+        theirs.tasks.push(task("Theirs"));
+
+        let (merged, conflicts) = merge(&base, &ours, &theirs);
+        assert!(conflicts.is_empty(), "{:?}", conflicts);
+        assert_eq!(merged.tasks.iter().map(|task| task.name.as_str()).collect::<Vec<_>>(), ["Shared", "Ours", "Theirs"]);
+        assert_eq!(merged.tasks[0].tags, [home, garden]);
+        assert_eq!(merged.tags.len(), 3);
+        assert_eq!(merged.tasks[1].uuid, ours.tasks[1].uuid);
+    }
+}
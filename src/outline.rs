@@ -0,0 +1,398 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use uuid::Uuid;
+use crate::{Completion, Priority, TODOList, Task};
+use crate::render;
+
+pub struct Node {
+    pub task: Task,
+    pub complete: bool,
+    pub depth: usize,
+    pub line: usize,
+    pub id: Option<String>,
+    pub due_given: bool
+}
+
+pub fn build(nodes: Vec<Node>, problems: &mut Vec<String>) -> Vec<Task> {
+    // Builds a tree of tasks from a flat list of nodes, in which each node is a subtask of the closest preceding node with a smaller depth. Checked-off nodes become complete tasks, unless they have subtasks which aren't checked off, since faros can't represent those.
+    let mut nodes = nodes.into_iter().peekable();
+    let mut tasks = Vec::new();
+    while nodes.peek().is_some() {
+        tasks.append(&mut take(&mut nodes, 0, problems));
+    }
+    tasks
+}
+
+fn take(nodes: &mut std::iter::Peekable<std::vec::IntoIter<Node>>, depth: usize, problems: &mut Vec<String>) -> Vec<Task> {
+    let mut tasks = Vec::new();
+    while let Some(node) = nodes.next_if(|node| node.depth >= depth) {
+        let children = take(nodes, node.depth + 1, problems);
+        let mut task = node.task;
+        if node.complete && children.iter().any(|child| matches!(child.completion, Completion::Incomplete(_))) {
+            problems.push(format!("line {}: {} is checked off, but has subtasks which aren't, so it was imported as incomplete", node.line, task.name));
+            task.completion = Completion::Incomplete(children);
+        } else if node.complete {
+            for child in &children {
+                problems.push(format!("line {}: {} is a subtask of {}, which is checked off, so it was left out", node.line, child.name, task.name));
+            }
+            task.completion = Completion::Complete;
+        } else {
+            task.completion = Completion::Incomplete(children);
+        }
+        tasks.push(task);
+    }
+    tasks
+}
+
+fn export_tree(task: &Task, depth: usize, todo_list: &TODOList, line: &dyn Fn(&Task, usize, &[&str]) -> String) -> String {
+    let mut text = line(task, depth, &render::tag_names(task, &todo_list.tags));
+    for child in task.children() {
+        text.push_str(&export_tree(child, depth + 1, todo_list, line));
+    }
+    text
+}
+
+fn markdown_line(task: &Task, depth: usize, tags: &[&str], cookie: bool) -> String {
+    let checkbox = match task.completion {
+        Completion::Complete => "[x]",
+        Completion::Incomplete(_) => "[ ]"
+    };
+    let priority = if cookie { format!("[#{}] ", priority_letter(task.priority)) } else { String::new() };
+    let tags = tags.iter().map(|tag| format!(" #{}", tag.replace(' ', "_"))).collect::<String>();
+    format!("{}- {} {}{} (due {}){}", "  ".repeat(depth), checkbox, priority, escape(&task.name), task.due_date.format("%Y-%m-%d %H:%M"), tags)
+}
+
+fn priority_letter(priority: Priority) -> &'static str {
+    // Priorities are written as org-mode's priority cookies, such as [#A], in Markdown as well as in org-mode.
+    match priority {
+        Priority::High => "A",
+        Priority::Medium => "B",
+        Priority::Low => "C"
+    }
+}
+
+fn strip_cookie(text: &str) -> (Option<Priority>, &str) {
+    for (cookie, priority) in [("[#A]", Priority::High), ("[#B]", Priority::Medium), ("[#C]", Priority::Low)].iter() {
+        if let Some(rest) = text.strip_prefix(cookie) {
+            return (Some(*priority), rest.trim_start());
+        }
+    }
+    (None, text)
+}
+
+fn escape(name: &str) -> String {
+    // Backslashes, hashes and "(due" in a task's name are escaped as Markdown does, so that a name such as "Fix issue #42" isn't read back as having a tag.
+    name.replace('\\', "\\\\").replace('#', "\\#").replace("(due ", "\\(due ")
+}
+
+fn unescape(text: &str) -> String {
+    // Removes the backslash from before any punctuation, as Markdown does, leaving other backslashes alone.
+    let mut name = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(next) if c == '\\' && next.is_ascii_punctuation() => name.push(chars.next().unwrap()),
+            _ => name.push(c)
+        }
+    }
+    name
+}
+
+pub fn export_markdown(roots: &[&Task], todo_list: &TODOList) -> String {
+    // Writes tasks as a nested Markdown checklist, indented by two spaces per level. Descriptions are left out, as a checklist has nowhere to put them.
+    roots.iter().map(|root| export_tree(root, 0, todo_list, &|task, depth, tags| format!("{}\n", markdown_line(task, depth, tags, true)))).collect()
+}
+
+pub fn export_outline(roots: &[&Task], todo_list: &TODOList) -> String {
+    // Writes tasks as a Markdown checklist in which each task is followed by its short ID in a comment, so that the tasks can be told apart when the checklist is read back in, however it has been rearranged.
+    roots.iter().map(|root| export_tree(root, 0, todo_list, &|task, depth, tags| {
+        format!("{} <!-- {} -->\n", markdown_line(task, depth, tags, false), task.short_id())
+    })).collect()
+}
+
+pub fn export_org(roots: &[&Task], todo_list: &TODOList) -> String {
+    // Writes tasks as org-mode headlines, with TODO and DONE keywords, priority cookies, tags and DEADLINE timestamps. Descriptions become the body of each headline.
+    roots.iter().map(|root| export_tree(root, 0, todo_list, &|task, depth, tags| {
+        let keyword = match task.completion {
+            Completion::Complete => "DONE",
+            Completion::Incomplete(_) => "TODO"
+        };
+        let priority = priority_letter(task.priority);
+        let tags = if tags.is_empty() {
+            String::new()
+        } else {
+            format!(" :{}:", tags.iter().map(|tag| tag.replace(' ', "_")).collect::<Vec<_>>().join(":"))
+        };
+        let indent = " ".repeat(depth + 2);
+        let mut text = format!("{} {} [#{}] {}{}\n", "*".repeat(depth + 1), keyword, priority, task.name, tags);
+        text.push_str(&format!("{}DEADLINE: <{}>\n", indent, task.due_date.format("%Y-%m-%d %a %H:%M")));
+        for line in task.description.lines() {
+            text.push_str(&format!("{}{}\n", indent, line));
+        }
+        text
+    })).collect()
+}
+
+fn parse_due(text: &str) -> Option<DateTime<Local>> {
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M")
+        .ok()
+        .and_then(|d| Local.from_local_datetime(&d).earliest())
+        .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok().map(crate::due_on))
+}
+
+fn tag_uuid(todo_list: &mut TODOList, tag: &str) -> Uuid {
+    // Spaces in tag names are written as underscores, so existing tags are matched either way.
+    match todo_list.tags.iter().find(|t| t.name == tag || t.name.replace(' ', "_") == tag) {
+        Some(existing) => existing.uuid,
+        None => todo_list.tag_uuid(tag)
+    }
+}
+
+pub fn import_markdown(contents: &str, todo_list: &mut TODOList) -> (Vec<Task>, Vec<String>) {
+    let (nodes, mut problems) = parse_markdown(contents, todo_list);
+    (build(nodes, &mut problems), problems)
+}
+
+pub fn parse_markdown(contents: &str, todo_list: &mut TODOList) -> (Vec<Node>, Vec<String>) {
+    // Reads a nested Markdown list, with or without checkboxes, as a tree of tasks. A priority cookie at the start of an item, and "(due YYYY-MM-DD HH:MM)", #tags and an ID in a comment at the end of it, are read back as well, so that exported checklists can be imported again.
+    let mut problems = Vec::new();
+    let mut nodes = Vec::new();
+    let mut indents: Vec<usize> = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let item = match trimmed.strip_prefix("- ").or_else(|| trimmed.strip_prefix("* ")).or_else(|| trimmed.strip_prefix("+ ")) {
+            Some(item) => item.trim(),
+            None => {
+                if !trimmed.is_empty() {
+                    problems.push(format!("line {}: \"{}\" is not a list item, so it was skipped", i + 1, trimmed));
+                }
+                continue;
+            }
+        };
+
+        let indent = line.len() - trimmed.len();
+        while indents.last().is_some_and(|&last| last >= indent) {
+            indents.pop();
+        }
+        let depth = indents.len();
+        indents.push(indent);
+
+        let (complete, text) = if let Some(text) = item.strip_prefix("[ ] ") {
+            (false, text)
+        } else if let Some(text) = item.strip_prefix("[x] ").or_else(|| item.strip_prefix("[X] ")) {
+            (true, text)
+        } else {
+            (false, item)
+        };
+        let (priority, mut text) = strip_cookie(text);
+        let mut id = None;
+        if let Some((rest, comment)) = text.strip_suffix("-->").and_then(|t| t.rsplit_once("<!--")) {
+            id = Some(String::from(comment.trim()));
+            text = rest.trim_end();
+        }
+        let mut tags = Vec::new();
+        while let Some((rest, tag)) = text.rsplit_once(" #").filter(|(_, tag)| !tag.is_empty() && !tag.contains(' ')) {
+            tags.insert(0, tag);
+            text = rest;
+        }
+        let mut due_date = crate::default_due_date();
+        let mut due_given = false;
+        if let Some((rest, due)) = text.strip_suffix(')').and_then(|t| t.rsplit_once(" (due ")) {
+            match parse_due(due) {
+                Some(date) => {
+                    due_date = date;
+                    due_given = true;
+                    text = rest;
+                },
+                None => problems.push(format!("line {}: \"{}\" is not a valid date, so the task was given the default due date", i + 1, due))
+            }
+        }
+
+        let mut task = Task::new(unescape(text.trim()), String::new(), priority.unwrap_or(crate::config::defaults().priority), due_date);
+        task.tags = tags.into_iter().map(|tag| tag_uuid(todo_list, tag)).collect();
+        nodes.push(Node { task, complete, depth, line: i + 1, id, due_given });
+    }
+    (nodes, problems)
+}
+
+pub fn import_org(contents: &str, todo_list: &mut TODOList) -> (Vec<Task>, Vec<String>) {
+    // Reads org-mode headlines as a tree of tasks. TODO and DONE keywords, priority cookies, tags and DEADLINE timestamps are read, and any other text beneath a headline becomes its description.
+    let mut problems = Vec::new();
+    let mut nodes: Vec<Node> = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let stars = line.chars().take_while(|&c| c == '*').count();
+        if stars == 0 || !line[stars..].starts_with(' ') {
+            let body = line.trim();
+            match nodes.last_mut() {
+                Some(node) => {
+                    if let Some(deadline) = body.strip_prefix("DEADLINE: <").and_then(|d| d.split_once('>')).map(|(d, _)| d) {
+                        // Timestamps look like <2020-01-01 Wed 12:00>, where the day of the week and the time are optional.
+                        let mut parts = deadline.split_whitespace();
+                        let date = parts.next().unwrap_or("");
+                        let time = parts.find(|part| part.contains(':'));
+                        match parse_due(&time.map_or_else(|| String::from(date), |t| format!("{} {}", date, t))) {
+                            Some(due_date) => {
+                                node.task.due_date = due_date;
+                                node.due_given = true;
+                            },
+                            None => problems.push(format!("line {}: \"{}\" is not a valid timestamp, so the task was given the default due date", i + 1, deadline))
+                        }
+                    } else if !body.is_empty() || !node.task.description.is_empty() {
+                        if !node.task.description.is_empty() {
+                            node.task.description.push('\n');
+                        }
+                        node.task.description.push_str(body);
+                    }
+                },
+                None if body.is_empty() || body.starts_with('#') => (),
+                None => problems.push(format!("line {}: \"{}\" is not part of a headline, so it was skipped", i + 1, body))
+            }
+            continue;
+        }
+
+        let mut title = line[stars..].trim();
+        let complete = match title.split_once(' ').map_or((title, ""), |split| split) {
+            ("TODO", rest) => {
+                title = rest.trim_start();
+                false
+            },
+            ("DONE", rest) => {
+                title = rest.trim_start();
+                true
+            },
+            _ => false
+        };
+        let (priority, rest) = strip_cookie(title);
+        title = rest;
+        let mut tags = Vec::new();
+        if let Some((rest, tag_list)) = title.rsplit_once(char::is_whitespace).filter(|(_, t)| t.len() > 1 && t.starts_with(':') && t.ends_with(':')) {
+            tags = tag_list.split(':').filter(|tag| !tag.is_empty()).collect();
+            title = rest.trim_end();
+        }
+
+        let mut task = Task::new(String::from(title), String::new(), priority.unwrap_or(crate::config::defaults().priority), crate::default_due_date());
+        task.tags = tags.into_iter().map(|tag| tag_uuid(todo_list, tag)).collect();
+        nodes.push(Node { task, complete, depth: stars - 1, line: i + 1, id: None, due_given: false });
+    }
+    for node in &mut nodes {
+        node.task.description = String::from(node.task.description.trim_end());
+    }
+    (build(nodes, &mut problems), problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty() -> TODOList {
+        TODOList { tasks: Vec::new(), tags: Vec::new() }
+    }
+
+    fn sample() -> TODOList {
+        let mut todo_list = empty();
+        let due_date = Local.ymd(2030, 3, 4).and_hms(17, 30, 0);
+        let mut parent = Task::new(String::from("Plan the trip"), String::from("Somewhere warm\nIn May"), Priority::High, due_date);
+        parent.tags = vec![todo_list.tag_uuid("long weekend")];
+        let mut flights = Task::new(String::from("Book flights"), String::new(), Priority::Low, due_date);
+        flights.completion = Completion::Complete;
+        let mut hotel = Task::new(String::from("Book a hotel"), String::new(), Priority::Medium, due_date);
+        hotel.add_subtask(Task::new(String::from("Compare prices"), String::new(), Priority::Medium, due_date));
+        parent.add_subtask(flights);
+        parent.add_subtask(hotel);
+        todo_list.tasks.push(parent);
+        todo_list
+    }
+
+    fn shape(tasks: &[Task], depth: usize) -> Vec<String> {
+        // Describes a tree of tasks as one line per task, giving its depth, whether it is complete and its name.
+        tasks.iter().flat_map(|task| {
+            let mut lines = vec![format!("{}{} {}", "  ".repeat(depth), if matches!(task.completion, Completion::Complete) { "x" } else { "-" }, task.name)];
+            lines.extend(shape(task.children(), depth + 1));
+            lines
+        }).collect()
+    }
+
+    #[test]
+    fn markdown_round_trip() {
+        let mut todo_list = sample();
+        let roots = todo_list.tasks.iter().collect::<Vec<_>>();
+        let exported = export_markdown(&roots, &todo_list);
+        let (tasks, problems) = import_markdown(&exported, &mut todo_list);
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(shape(&tasks, 0), shape(&todo_list.tasks, 0));
+        assert_eq!(tasks[0].due_date, todo_list.tasks[0].due_date);
+        assert_eq!(tasks[0].tags, todo_list.tasks[0].tags);
+        assert!(tasks[0].priority == Priority::High);
+        assert!(tasks[0].children()[0].priority == Priority::Low);
+        assert_eq!(tasks[0].description, "");
+        assert_eq!(tasks[0].children()[1].children()[0].name, "Compare prices");
+    }
+
+    #[test]
+    fn outline_ids() {
+        let mut todo_list = sample();
+        let roots = todo_list.tasks.iter().collect::<Vec<_>>();
+        let exported = export_outline(&roots, &todo_list);
+        let (nodes, problems) = parse_markdown(&exported, &mut todo_list);
+        assert!(problems.is_empty(), "{:?}", problems);
+        let ids = nodes.iter().map(|node| node.id.clone().unwrap()).collect::<Vec<_>>();
+        let expected = todo_list.flattened().iter().map(|task| task.short_id()).collect::<Vec<_>>();
+        assert_eq!(ids, expected);
+        assert_eq!(nodes.iter().map(|node| node.depth).collect::<Vec<_>>(), [0, 1, 1, 2]);
+    }
+
+    #[test]
+    fn names_which_look_like_tags_or_dates() {
+        let mut todo_list = empty();
+        let names = ["Fix issue #42", "Call Bob (due back Monday)", "C:\\Temp\\#1 \\(due"];
+        for name in names.iter() {
+            todo_list.tasks.push(Task::new(String::from(*name), String::new(), Priority::Medium, crate::default_due_date()));
+        }
+        let roots = todo_list.tasks.iter().collect::<Vec<_>>();
+        let exported = export_outline(&roots, &todo_list);
+        let (nodes, problems) = parse_markdown(&exported, &mut todo_list);
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(nodes.iter().map(|node| node.task.name.as_str()).collect::<Vec<_>>(), names);
+        assert!(nodes.iter().all(|node| node.task.tags.is_empty() && node.due_given));
+        assert!(todo_list.tags.is_empty());
+    }
+
+    #[test]
+    fn org_round_trip() {
+        let mut todo_list = sample();
+        let roots = todo_list.tasks.iter().collect::<Vec<_>>();
+        let exported = export_org(&roots, &todo_list);
+        let (tasks, problems) = import_org(&exported, &mut todo_list);
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(shape(&tasks, 0), shape(&todo_list.tasks, 0));
+        assert_eq!(tasks[0].description, "Somewhere warm\nIn May");
+        assert!(tasks[0].priority == Priority::High);
+        assert!(tasks[0].children()[0].priority == Priority::Low);
+        assert_eq!(tasks[0].due_date, todo_list.tasks[0].due_date);
+        assert_eq!(render::tag_names(&tasks[0], &todo_list.tags), ["long weekend"]);
+    }
+
+    #[test]
+    fn malformed_markdown() {
+        let mut todo_list = empty();
+        let contents = "# Heading\n- [x] Parent\n  - [ ] Child\n- Plain (due next week)\n- [x] Done\n  - [x] Also done\n";
+        let (tasks, problems) = import_markdown(contents, &mut todo_list);
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        for (problem, line) in problems.iter().zip(&[1, 4, 2, 5]) {
+            assert!(problem.starts_with(&format!("line {}:", line)), "{}", problem);
+        }
+        assert_eq!(shape(&tasks, 0), ["- Parent", "  - Child", "- Plain (due next week)", "x Done"]);
+    }
+
+    #[test]
+    fn malformed_org() {
+        let mut todo_list = empty();
+        let contents = "#+TITLE: Tasks\nStray text\n* TODO Parent\n  DEADLINE: <someday>\n** Child\n*Bold* isn't a headline\n";
+        let (tasks, problems) = import_org(contents, &mut todo_list);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].starts_with("line 2:") && problems[1].starts_with("line 4:"));
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].due_date, crate::default_due_date());
+        assert!(tasks[0].priority == crate::config::defaults().priority);
+        assert_eq!(tasks[0].children()[0].description, "*Bold* isn't a headline");
+    }
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use std::{io, str};
+use uuid::Uuid;
+use crate::{Completion, TODOList, Task};
+use crate::{render, tree};
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Table,
+    Long,
+    Json,
+    Csv,
+    Tsv
+}
+
+impl str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(Format::Table),
+            "long" => Ok(Format::Long),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            _ => Err(format!("Unknown format \"{}\", expected table, long, json, csv or tsv.", s))
+        }
+    }
+}
+
+impl Format {
+    pub fn machine_readable(self) -> bool {
+        matches!(self, Format::Json | Format::Csv | Format::Tsv)
+    }
+}
+
+// The stable, machine-readable representation of a task. The JSON schema is documented in the README, so fields should only ever be added, never renamed or removed.
+#[derive(Serialize)]
+pub struct TaskRecord {
+    pub id: Uuid,
+    pub short_id: String,
+    pub name: String,
+    pub description: String,
+    pub path: Vec<String>,
+    pub parent: Option<Uuid>,
+    pub priority: String,
+    pub due: String,
+    pub complete: bool,
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtasks: Option<Vec<TaskRecord>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub list: Option<String>
+}
+
+impl TaskRecord {
+    pub fn new(task: &Task, todo_list: &TODOList) -> TaskRecord {
+        let path = todo_list.path(task.uuid);
+        TaskRecord {
+            id: task.uuid,
+            short_id: task.short_id(),
+            name: task.name.clone(),
+            description: task.description.clone(),
+            path: path.iter().map(|t| t.name.clone()).collect(),
+            parent: path.len().checked_sub(2).map(|i| path[i].uuid),
+            priority: task.priority.to_string(),
+            due: task.due_date.to_rfc3339(),
+            complete: matches!(task.completion, Completion::Complete),
+            tags: render::tag_names(task, &todo_list.tags).into_iter().map(String::from).collect(),
+            subtasks: None,
+            list: None
+        }
+    }
+
+    pub fn tree(task: &Task, todo_list: &TODOList, keep: &dyn Fn(&Task) -> bool) -> TaskRecord {
+        // Builds a record for a task and, recursively, for each of its subtasks which are kept or have kept subtasks.
+        let mut record = TaskRecord::new(task, todo_list);
+        record.subtasks = Some(task.children()
+                                   .iter()
+                                   .filter(|child| tree::retained(child, keep))
+                                   .map(|child| TaskRecord::tree(child, todo_list, keep))
+                                   .collect());
+        record
+    }
+
+    pub fn in_list(&mut self, name: &str) {
+        // Marks a record, and those of its subtasks, with the named list it came from, for listings which combine several lists.
+        self.list = Some(String::from(name));
+        for subtask in self.subtasks.iter_mut().flatten() {
+            subtask.in_list(name);
+        }
+    }
+
+    fn flattened(&self) -> Vec<&TaskRecord> {
+        let mut records = vec![self];
+        for subtask in self.subtasks.iter().flatten() {
+            records.append(&mut subtask.flattened());
+        }
+        records
+    }
+}
+
+pub fn print_records(records: &[TaskRecord], format: Format) -> Result<(), String> {
+    // Prints records as a JSON array, or as CSV or TSV with one row per task, and refuses the formats meant for people. Nested subtasks are flattened into rows of their own, since their parent and path columns already describe the tree.
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(io::stdout(), records).map_err(|_| String::from("Your tasks could not be serialized."))?;
+            println!();
+        },
+        Format::Csv | Format::Tsv => {
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(if format == Format::Csv { b',' } else { b'\t' })
+                .from_writer(io::stdout());
+            // Records from several lists get a list column, which is added after the others so that scripts reading columns by position aren't affected.
+            let records = records.iter().flat_map(|r| r.flattened()).collect::<Vec<_>>();
+            let with_list = records.iter().any(|r| r.list.is_some());
+            let mut header = ["id", "short_id", "path", "name", "description", "parent", "priority", "due", "complete", "tags"].iter().map(|s| s.to_string()).collect::<Vec<_>>();
+            if with_list {
+                header.push(String::from("list"));
+            }
+            let rows = std::iter::once(header)
+                .chain(records.iter().map(|r| {
+                    let mut row = vec![
+                        r.id.to_string(),
+                        r.short_id.clone(),
+                        r.path.join("/"),
+                        r.name.clone(),
+                        r.description.clone(),
+                        r.parent.map(|p| p.to_string()).unwrap_or_default(),
+                        r.priority.clone(),
+                        r.due.clone(),
+                        r.complete.to_string(),
+                        r.tags.join(",")
+                    ];
+                    if with_list {
+                        row.push(r.list.clone().unwrap_or_default());
+                    }
+                    row
+                }));
+            for row in rows {
+                writer.write_record(&row).map_err(|_| String::from("Your tasks could not be written."))?;
+            }
+            writer.flush().map_err(|_| String::from("Your tasks could not be written."))?;
+        },
+        Format::Table | Format::Long => return Err(format!("Tasks can only be printed as records in JSON, CSV or TSV, not as a {}.", if format == Format::Table { "table" } else { "long list" }))
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_for_people() {
+        assert!(print_records(&[], Format::Table).is_err());
+        assert!(print_records(&[], Format::Long).is_err());
+        assert!(!Format::Table.machine_readable() && Format::Tsv.machine_readable());
+    }
+}
@@ -0,0 +1,198 @@
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::{env, str};
+use std::io::{self, IsTerminal};
+use crate::{Completion, Priority, Tag, Task};
+use crate::config::{colors, ColorMode};
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Column {
+    Id,
+    Status,
+    Name,
+    Description,
+    Due,
+    Priority,
+    Progress,
+    Tags
+}
+
+const RESET: &str = "\x1b[0m";
+
+impl str::FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Column, String> {
+        match s.to_lowercase().as_str() {
+            "id" => Ok(Column::Id),
+            "status" => Ok(Column::Status),
+            "name" => Ok(Column::Name),
+            "description" | "desc" => Ok(Column::Description),
+            "due" => Ok(Column::Due),
+            "priority" => Ok(Column::Priority),
+            "progress" => Ok(Column::Progress),
+            "tags" => Ok(Column::Tags),
+            _ => Err(format!("Unknown column \"{}\".", s))
+        }
+    }
+}
+
+impl Column {
+    fn header(self) -> &'static str {
+        match self {
+            Column::Id => "ID",
+            Column::Status => "",
+            Column::Name => "NAME",
+            Column::Description => "DESCRIPTION",
+            Column::Due => "DUE",
+            Column::Priority => "PRIORITY",
+            Column::Progress => "DONE",
+            Column::Tags => "TAGS"
+        }
+    }
+
+    fn shrinkable(self) -> bool {
+        // Only free-form text is truncated to fit the terminal; everything else is short and is always shown in full.
+        matches!(self, Column::Name | Column::Description | Column::Tags)
+    }
+
+    fn cell(self, task: &Task, tags: &[Tag], now: DateTime<Local>) -> (String, &'static str) {
+        // Returns the text of a cell along with the escape code used to color it.
+        match self {
+            Column::Id => (task.short_id(), ""),
+            Column::Status => match task.completion {
+                Completion::Complete => (String::from("✓"), ""),
+                Completion::Incomplete(_) => (String::new(), "")
+            },
+            Column::Name => (task.name.clone(), ""),
+            Column::Description => (task.description.clone(), ""),
+            Column::Due => {
+                let overdue = task.due_date < now && matches!(task.completion, Completion::Incomplete(_));
+                (relative_due(task.due_date, now), if overdue { colors().overdue.code() } else { "" })
+            },
+            Column::Priority => (task.priority.to_string(), match task.priority {
+                Priority::High => colors().high.code(),
+                Priority::Medium => colors().medium.code(),
+                Priority::Low => colors().low.code()
+            }),
+            Column::Progress => {
+                let (complete, total) = task.progress();
+                (if total > 0 { format!("{}/{}", complete, total) } else { String::new() }, "")
+            },
+            Column::Tags => (tag_names(task, tags).join(","), "")
+        }
+    }
+}
+
+pub fn tag_names<'a>(task: &Task, tags: &'a [Tag]) -> Vec<&'a str> {
+    tags.iter()
+        .filter(|tag| task.tags.contains(&tag.uuid))
+        .map(|tag| tag.name.as_str())
+        .collect()
+}
+
+pub fn relative_due(due: DateTime<Local>, now: DateTime<Local>) -> String {
+    // Describes a due date relative to now, such as "in 2d" or "3h overdue", using the largest whole unit.
+    let delta = due - now;
+    let magnitude = if delta < Duration::zero() { -delta } else { delta };
+    let amount = if magnitude.num_days() > 0 {
+        format!("{}d", magnitude.num_days())
+    } else if magnitude.num_hours() > 0 {
+        format!("{}h", magnitude.num_hours())
+    } else if magnitude.num_minutes() > 0 {
+        format!("{}m", magnitude.num_minutes())
+    } else {
+        return String::from("now");
+    };
+    if delta < Duration::zero() {
+        format!("{} overdue", amount)
+    } else {
+        format!("in {}", amount)
+    }
+}
+
+pub fn use_color() -> bool {
+    // By default, colors are only used when writing to a terminal, and never when NO_COLOR is set to a non-empty value (see https://no-color.org). The configuration file can turn them on or off regardless.
+    match colors().mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+    }
+}
+
+fn terminal_width() -> Option<usize> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    terminal_size::terminal_size().map(|(terminal_size::Width(w), _)| w as usize)
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        String::from(text)
+    } else if width == 0 {
+        String::new()
+    } else {
+        format!("{}…", text.chars().take(width - 1).collect::<String>())
+    }
+}
+
+pub fn paint(text: &str, style: &str, color: bool) -> String {
+    if color && !style.is_empty() {
+        format!("{}{}{}", style, text, RESET)
+    } else {
+        String::from(text)
+    }
+}
+
+pub fn print_table(tasks: &[&Task], tags: &[Tag], columns: &[Column]) {
+    // Prints tasks as a table with a header row. When writing to a terminal, the widest free-form columns are truncated until the table fits.
+    let now = Local::now();
+    let color = use_color();
+    let rows = tasks.iter()
+                    .map(|task| columns.iter().map(|c| c.cell(task, tags, now)).collect::<Vec<_>>())
+                    .collect::<Vec<_>>();
+
+    let mut widths = columns.iter().enumerate().map(|(i, column)| {
+        rows.iter()
+            .map(|row| row[i].0.chars().count())
+            .chain(std::iter::once(column.header().chars().count()))
+            .max()
+            .unwrap_or(0)
+    }).collect::<Vec<_>>();
+
+    if let Some(max_width) = terminal_width() {
+        let separators = 2 * columns.len().saturating_sub(1);
+        while widths.iter().sum::<usize>() + separators > max_width {
+            let widest = columns.iter()
+                                .enumerate()
+                                .filter(|(i, column)| column.shrinkable() && widths[*i] > column.header().len().max(8))
+                                .max_by_key(|(i, _)| widths[*i])
+                                .map(|(i, _)| i);
+            match widest {
+                Some(i) => widths[i] -= 1,
+                None => break
+            }
+        }
+    }
+
+    let header = columns.iter()
+                        .zip(&widths)
+                        .map(|(column, width)| format!("{:<width$}", column.header(), width = width))
+                        .collect::<Vec<_>>()
+                        .join("  ");
+    println!("{}", paint(header.trim_end(), colors().header.code(), color));
+    for row in rows {
+        let line = row.iter()
+                      .zip(&widths)
+                      .enumerate()
+                      .map(|(i, ((text, style), width))| {
+                          let padding = if i + 1 == columns.len() { 0 } else { *width };
+                          paint(&format!("{:<padding$}", truncate(text, *width), padding = padding), style, color)
+                      })
+                      .collect::<Vec<_>>()
+                      .join("  ");
+        println!("{}", line);
+    }
+}
@@ -0,0 +1,205 @@
+use regex::{Regex, RegexBuilder};
+use crate::{TODOList, Task};
+use crate::render;
+use crate::config::colors;
+
+pub enum Matcher {
+    Pattern(Regex),
+    Fuzzy(String)
+}
+
+pub struct Hit<'a> {
+    pub task: &'a Task,
+    pub score: i64,
+    pub fields: Vec<FieldMatch>
+}
+
+pub struct FieldMatch {
+    pub field: &'static str,
+    pub text: String,
+    pub ranges: Vec<(usize, usize)>
+}
+
+impl Matcher {
+    pub fn substring(query: &str) -> Matcher {
+        Matcher::Pattern(RegexBuilder::new(&regex::escape(query)).case_insensitive(true).build().unwrap())
+    }
+
+    pub fn regex(query: &str) -> Result<Matcher, String> {
+        RegexBuilder::new(query)
+            .case_insensitive(true)
+            .build()
+            .map(Matcher::Pattern)
+            .map_err(|err| format!("Invalid regular expression: {}", err))
+    }
+
+    pub fn fuzzy(query: &str) -> Matcher {
+        Matcher::Fuzzy(query.to_lowercase())
+    }
+
+    pub fn find(&self, text: &str) -> Option<(i64, Vec<(usize, usize)>)> {
+        // Returns a score for how well the text matches, along with the byte ranges of the text which matched.
+        match self {
+            Matcher::Pattern(regex) => {
+                let ranges = regex.find_iter(text)
+                                  .filter(|m| !m.is_empty())
+                                  .map(|m| (m.start(), m.end()))
+                                  .collect::<Vec<_>>();
+                if ranges.is_empty() {
+                    return None;
+                }
+                // Matches spanning the whole text, or starting it, are better than matches in the middle of it.
+                let bonus = match ranges[0] {
+                    (0, end) if end == text.len() => 10,
+                    (0, _) => 5,
+                    _ => 0
+                };
+                Some((ranges.len() as i64 + bonus, ranges))
+            },
+            Matcher::Fuzzy(query) => fuzzy(query, text)
+        }
+    }
+}
+
+fn fuzzy(query: &str, text: &str) -> Option<(i64, Vec<(usize, usize)>)> {
+    // Matches the characters of the query, in order, anywhere in the text. Consecutive characters and characters at the start of a word score more highly, and unmatched characters in the text score slightly lower.
+    let mut query = query.chars().peekable();
+    let mut score = 0;
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut previous: Option<char> = None;
+    for (i, c) in text.char_indices() {
+        match query.peek() {
+            Some(q) if c.to_lowercase().eq(q.to_lowercase()) => {
+                query.next();
+                score += 1;
+                if previous.is_none_or(|p| !p.is_alphanumeric()) {
+                    score += 3;
+                }
+                match ranges.last_mut() {
+                    Some(range) if range.1 == i => {
+                        range.1 = i + c.len_utf8();
+                        score += 5;
+                    },
+                    _ => ranges.push((i, i + c.len_utf8()))
+                }
+            },
+            Some(_) => score -= 1,
+            None => break
+        }
+        previous = Some(c);
+    }
+    if query.peek().is_some() || ranges.is_empty() {
+        None
+    } else {
+        Some((score, ranges))
+    }
+}
+
+pub fn search<'a>(todo_list: &'a TODOList, matcher: &Matcher) -> Vec<Hit<'a>> {
+    // Searches task names, descriptions and tag names, ranking tasks by how well they match. Matches in names are weighted more heavily than matches in tags, which are in turn weighted more heavily than matches in descriptions.
+    let mut hits = todo_list.flattened().into_iter().filter_map(|task| {
+        let tags = render::tag_names(task, &todo_list.tags).join(", ");
+        let fields = vec![("name", 3, task.name.clone()), ("tags", 2, tags), ("description", 1, task.description.clone())]
+            .into_iter()
+            .filter_map(|(field, weight, text)| {
+                matcher.find(&text).map(|(score, ranges)| (field, weight * score, text, ranges))
+            })
+            .collect::<Vec<_>>();
+        if fields.is_empty() {
+            None
+        } else {
+            Some(Hit {
+                task,
+                score: fields.iter().map(|f| f.1).sum(),
+                fields: fields.into_iter().map(|(field, _, text, ranges)| FieldMatch { field, text, ranges }).collect()
+            })
+        }
+    }).collect::<Vec<_>>();
+    hits.sort_by_key(|hit| (-hit.score, hit.task.due_date));
+    hits
+}
+
+pub fn highlight(text: &str, ranges: &[(usize, usize)], color: bool) -> String {
+    // Highlights the matched parts of a text. Without colors the text is left as it is, so that it can be piped elsewhere.
+    if !color {
+        return String::from(text);
+    }
+    let mut highlighted = String::new();
+    let mut last = 0;
+    for &(start, end) in ranges {
+        highlighted.push_str(&text[last..start]);
+        highlighted.push_str(&render::paint(&text[start..end], colors().highlight.code(), true));
+        last = end;
+    }
+    highlighted.push_str(&text[last..]);
+    highlighted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use crate::Priority;
+
+    fn task(name: &str, description: &str, days: i64) -> Task {
+        Task::new(String::from(name), String::from(description), Priority::Medium, crate::default_due_date() + Duration::days(days))
+    }
+
+    #[test]
+    fn substrings_and_regexes() {
+        let milk = Matcher::substring("MILK");
+        assert_eq!(milk.find("Buy milk"), Some((1, vec![(4, 8)])));
+        assert_eq!(milk.find("Milk run"), Some((6, vec![(0, 4)])));
+        assert_eq!(milk.find("milk"), Some((11, vec![(0, 4)])));
+        assert_eq!(milk.find("Buy bread"), None);
+        // Substrings are matched literally, even when they hold characters with a meaning in regular expressions.
+        assert_eq!(Matcher::substring("a.b").find("axb"), None);
+        assert_eq!(Matcher::substring("a.b").find("a.b"), Some((11, vec![(0, 3)])));
+
+        assert_eq!(Matcher::regex("b.y").unwrap().find("Buy a bay"), Some((7, vec![(0, 3), (6, 9)])));
+        assert_eq!(Matcher::regex("x*").unwrap().find("Buy"), None);
+        assert!(Matcher::regex("(").err().unwrap().starts_with("Invalid regular expression:"));
+    }
+
+    #[test]
+    fn fuzzy_matches() {
+        assert_eq!(Matcher::fuzzy("BM").find("Buy milk"), Some((5, vec![(0, 1), (4, 5)])));
+        assert_eq!(Matcher::fuzzy("bu").find("Buy milk"), Some((10, vec![(0, 2)])));
+        assert_eq!(Matcher::fuzzy("é").find("Café"), Some((-2, vec![(3, 5)])));
+        assert_eq!(Matcher::fuzzy("mb").find("Buy milk"), None);
+        assert_eq!(Matcher::fuzzy("").find("Buy milk"), None);
+    }
+
+    #[test]
+    fn ranking() {
+        let mut todo_list = TODOList { tasks: Vec::new(), tags: Vec::new() };
+        let mut tagged = task("Errands", "", 0);
+        tagged.tags = vec![todo_list.tag_uuid("milk run")];
+        todo_list.tasks = vec![task("Buy milk", "", 2), task("Shop", "Buy milk at the shop", 0), tagged, task("Buy bread", "", 0), task("Buy milk", "", 1)];
+
+        // Tags weigh twice as much as descriptions, and names three times as much, and tasks which match equally well are ordered by due date.
+        let hits = search(&todo_list, &Matcher::substring("milk"));
+        let found = hits.iter().map(|hit| (hit.task.name.as_str(), hit.task.due_date, hit.score)).collect::<Vec<_>>();
+        let due = |days| crate::default_due_date() + Duration::days(days);
+        assert_eq!(found, [("Errands", due(0), 12), ("Buy milk", due(1), 3), ("Buy milk", due(2), 3), ("Shop", due(0), 1)]);
+        assert_eq!(hits[0].fields.len(), 1);
+        assert_eq!((hits[0].fields[0].field, hits[0].fields[0].text.as_str(), &hits[0].fields[0].ranges[..]), ("tags", "milk run", &[(0, 4)][..]));
+        assert_eq!(hits[3].fields[0].field, "description");
+
+        // Matches in several fields add up.
+        let hits = search(&todo_list, &Matcher::substring("shop"));
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].score, 11 * 3 + 1);
+        assert_eq!(hits[0].fields.iter().map(|field| field.field).collect::<Vec<_>>(), ["name", "description"]);
+    }
+
+    #[test]
+    fn highlighting() {
+        let ranges = [(0, 3), (4, 8)];
+        assert_eq!(highlight("Buy milk", &ranges, false), "Buy milk");
+        assert_eq!(highlight("Buy milk", &[], true), "Buy milk");
+        let paint = |text| render::paint(text, colors().highlight.code(), true);
+        assert_eq!(highlight("Buy milk", &ranges, true), format!("{} {}", paint("Buy"), paint("milk")));
+        assert_eq!(highlight("Café au lait", &[(3, 5)], true), format!("Caf{} au lait", paint("é")));
+    }
+}
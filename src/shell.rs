@@ -0,0 +1,425 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use clap::ErrorKind;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use uuid::Uuid;
+use crate::{Config, TODOList};
+use crate::{alias, completion, config};
+
+// Commands which work on something other than the list the shell has open, or which would change it behind the shell's back.
+const REFUSED: [&str; 5] = ["shell", "batch", "merge", "config", "sync"];
+// A batch also can't run commands which need someone at the terminal, or which change files other than its list, as those changes couldn't be rolled back.
+const REFUSED_IN_BATCH: [&str; 13] = ["shell", "batch", "merge", "config", "sync", "tui", "edit", "edit-tree", "move", "use", "list-create", "caldav", "completions"];
+
+const BUILTINS: [&str; 5] = ["cd", "save", "help", "exit", "quit"];
+
+const HELP: &str = "
+SHELL COMMANDS:
+    cd [task]     Chooses the task which add subtask adds to when it isn't given a parent. cd .. chooses the
+                  task's parent, and cd on its own goes back to the top.
+    save          Saves your changes.
+    exit, quit    Saves your changes and leaves the shell, as does Ctrl-D.
+
+Any other line is run as a faros command, such as `add subtask -n Draft` or `list --tree`.";
+
+struct Session<'a> {
+    name: &'static str,
+    refused: &'static [&'static str],
+    config: &'a Config,
+    list_file: &'a Path,
+    todo_list: TODOList,
+    current: Option<Uuid>,
+    changed: Vec<PathBuf>,
+    messages: Vec<String>
+}
+
+struct Words {
+    todo_list: TODOList
+}
+
+pub fn run(config: &Config, list_file: &Path, todo_list: TODOList) -> Result<(), String> {
+    // Runs faros commands typed one line at a time against a TODO list which is only read once, and written when the shell is left or `save` is typed.
+    let mut editor = Editor::<Words, DefaultHistory>::new().map_err(|err| format!("The shell could not be started: {}", err))?;
+    editor.set_helper(Some(Words { todo_list: todo_list.clone() }));
+    let history = crate::data_dir().map(|dir| dir.join("shell_history"));
+    if let Some(history) = &history {
+        let _ = editor.load_history(history);
+    }
+    let mut session = Session {
+        name: "shell",
+        refused: &REFUSED,
+        config,
+        list_file,
+        todo_list,
+        current: None,
+        changed: vec![list_file.to_path_buf()],
+        messages: Vec::new()
+    };
+
+    println!("Type help for a list of commands, and exit or Ctrl-D to save your changes and leave.");
+    loop {
+        if let Some(words) = editor.helper_mut() {
+            words.todo_list = session.todo_list.clone();
+        }
+        let line = match editor.readline(&session.prompt()) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("Could not read from standard input: {}", err);
+                break;
+            }
+        };
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+        let words = match alias::split(&line) {
+            Ok(words) => words,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+        match words.first().map(String::as_str) {
+            None => (),
+            Some("exit") | Some("quit") => break,
+            Some("save") => match session.save() {
+                Ok(true) => println!("Saved your changes."),
+                Ok(false) => println!("There are no changes to save."),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    eprintln!("Your changes weren't saved, but they are kept, so save can be tried again.");
+                }
+            },
+            Some("help") if words.len() == 1 => {
+                let _ = crate::app().print_help();
+                println!("{}", HELP);
+            },
+            Some("cd") => {
+                session.cd(words.get(1).map(String::as_str));
+            },
+            Some(_) => {
+                session.command(words);
+            }
+        }
+    }
+
+    if let Some(history) = &history {
+        let _ = history.parent().map(fs::create_dir_all);
+        let _ = editor.save_history(history);
+    }
+    session.save()?;
+    Ok(())
+}
+
+pub fn batch(config: &Config, list_file: &Path, todo_list: TODOList, script: &str) -> Result<(), String> {
+    // Runs the commands in a script, one per line, against the TODO list, which is only written if every one of them succeeds. Blank lines and lines beginning with # are skipped, and cd works as it does in the shell.
+    crate::IN_BATCH.store(true, Ordering::Relaxed);
+    let mut session = Session {
+        name: "batch",
+        refused: &REFUSED_IN_BATCH,
+        config,
+        list_file,
+        todo_list,
+        current: None,
+        changed: vec![list_file.to_path_buf()],
+        messages: Vec::new()
+    };
+    for (i, line) in script.lines().enumerate() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let succeeded = match alias::split(line) {
+            Ok(words) if words[0] == "cd" => session.cd(words.get(1).map(String::as_str)),
+            Ok(words) => session.command(words),
+            Err(err) => {
+                eprintln!("{}", err);
+                false
+            }
+        };
+        if !succeeded {
+            return Err(format!("Line {} failed: {}\nNothing was changed, as a batch is applied all at once or not at all.", i + 1, line.trim()));
+        }
+    }
+    if !session.save()? {
+        println!("Nothing was changed.");
+    }
+    Ok(())
+}
+
+impl Session<'_> {
+    fn prompt(&self) -> String {
+        match self.current {
+            Some(uuid) => format!("faros:{}> ", self.todo_list.path(uuid).iter().map(|t| t.name.as_str()).collect::<Vec<_>>().join("/")),
+            None => String::from("faros> ")
+        }
+    }
+
+    fn save(&mut self) -> Result<bool, String> {
+        // Writes the TODO list and commits everything changed since it was last saved as one change to its history, returning whether there was anything to save. If saving fails, the changes are kept so that it can be tried again.
+        if self.messages.is_empty() {
+            return Ok(false);
+        }
+        let message = if self.messages.len() == 1 { self.messages[0].clone() } else { format!("{}: {}", self.name, self.messages.join("; ")) };
+        crate::save(self.config, &self.todo_list, &self.changed, &message)?;
+        self.messages.clear();
+        self.changed.truncate(1);
+        Ok(true)
+    }
+
+    fn cd(&mut self, target: Option<&str>) -> bool {
+        match target {
+            None | Some("/") => self.current = None,
+            Some("..") => {
+                let path = self.current.map(|uuid| self.todo_list.path(uuid)).unwrap_or_default();
+                self.current = path.len().checked_sub(2).map(|i| path[i].uuid);
+            },
+            Some(name) => {
+                match self.todo_list.task_from_name(name) {
+                    Ok(Some(task)) => self.current = Some(task.uuid),
+                    Ok(None) => {
+                        eprintln!("There is no task named {}", name);
+                        return false;
+                    },
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    fn command(&mut self, words: Vec<String>) -> bool {
+        // Runs a faros command. A command which fails leaves the TODO list as it was before it started. Commands which change other lists, such as move, write them straight away, so the shell's list is saved along with them.
+        let mut args = vec![String::from("faros")];
+        args.extend(words);
+        let args = match alias::expand(args, &config::aliases()) {
+            Ok(args) => args,
+            Err(err) => {
+                eprintln!("{}", err);
+                return false;
+            }
+        };
+        let matches = match crate::app().get_matches_from_safe(&args) {
+            Ok(matches) => matches,
+            Err(err) if err.kind == ErrorKind::HelpDisplayed || err.kind == ErrorKind::VersionDisplayed => {
+                println!("{}", err.message);
+                return true;
+            },
+            Err(err) => {
+                eprintln!("{}", err.message);
+                return false;
+            }
+        };
+        if crate::global_value(&matches, "list_file").is_some() || crate::global_value(&matches, "list_name").is_some() {
+            eprintln!("The {} works on the list it was started with. Start faros {} with --list or --file to work on another list.", self.name, self.name);
+            return false;
+        }
+        let command = matches.subcommand_name().unwrap_or_default();
+        if self.refused.contains(&command) {
+            eprintln!("faros {} can't be run from a {}.", command, self.name);
+            return false;
+        }
+
+        let before = self.todo_list.clone();
+        let written = self.changed.len();
+        if let Err(err) = crate::run(&matches, self.config, self.list_file, &mut self.todo_list, &mut self.changed, self.current) {
+            eprintln!("{}", err);
+            self.todo_list = before;
+            self.changed.truncate(written);
+            return false;
+        }
+        if serde_json::to_string(&before).ok() != serde_json::to_string(&self.todo_list).ok() || self.changed.len() > written {
+            self.messages.push(crate::change_message(&matches));
+        }
+        if self.changed.len() > written {
+            if let Err(err) = self.save() {
+                eprintln!("{}", err);
+                eprintln!("The other lists this command changed were saved, but this one wasn't. Type save to try again.");
+            }
+        }
+        if self.current.is_some_and(|uuid| self.todo_list.path(uuid).is_empty()) {
+            self.current = None;
+            println!("The current task is gone, so you're back at the top.");
+        }
+        true
+    }
+}
+
+fn word_start(line: &str) -> usize {
+    // Finds where the last word of a line begins, which may be inside quotes.
+    let mut start = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (_, '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, c) if c.is_whitespace() => start = i + c.len_utf8(),
+            _ => ()
+        }
+    }
+    start
+}
+
+fn quote(word: String) -> String {
+    if word.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '\\') {
+        format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        word
+    }
+}
+
+fn subcommands(words: &[String]) -> Option<Vec<String>> {
+    // Finds the subcommands which could follow the given words, if they name a command which has subcommands.
+    let names = match words {
+        [] => crate::COMMANDS.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+        [command] => crate::COMMANDS.iter().find(|(name, _)| name == command)?.1.to_vec(),
+        _ => return None
+    };
+    if names.is_empty() { None } else { Some(names.into_iter().map(String::from).collect()) }
+}
+
+impl Completer for Words {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        // Completes the names of commands, and the names of tasks, tags and lists in the same way as the completion scripts, but from the list the shell has open.
+        let line = &line[..pos];
+        let start = word_start(line);
+        let mut words = alias::split(&line[..start]).unwrap_or_default();
+        let prefix = completion::unquote(&line[start..]);
+        let mut candidates = match (words.first().map(String::as_str), subcommands(&words)) {
+            (Some("cd"), _) => completion::candidates(&self.todo_list, &completion::Kind::Task { incomplete_only: false }, Vec::new(), &prefix),
+            (_, Some(mut names)) => {
+                if words.is_empty() {
+                    names.extend(BUILTINS.iter().map(|name| String::from(*name)));
+                    names.extend(config::aliases().into_keys());
+                }
+                names.retain(|name| name.starts_with(&prefix));
+                names.sort();
+                names
+            },
+            (_, None) => {
+                words.insert(0, String::from("faros"));
+                let index = words.len();
+                words.push(String::from(&line[start..]));
+                crate::completions_for(index, words, Some(&self.todo_list))
+            }
+        };
+        candidates.dedup();
+        Ok((start, candidates.into_iter().map(quote).collect()))
+    }
+}
+
+impl Hinter for Words {
+    type Hint = String;
+}
+
+impl Highlighter for Words {}
+
+impl Validator for Words {}
+
+impl Helper for Words {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Datelike;
+
+    fn listed(args: &[&str]) -> Vec<String> {
+        // Reads the names of the subcommands which clap lists in the help for a command.
+        let mut args = args.to_vec();
+        args.insert(0, "faros");
+        args.push("--help");
+        let help = crate::app().get_matches_from_safe(args).err().unwrap().message;
+        let mut names = help.lines()
+                            .skip_while(|line| *line != "SUBCOMMANDS:")
+                            .skip(1)
+                            .filter(|line| line.starts_with("    ") && !line[4..].starts_with(' '))
+                            .filter_map(|line| line.split_whitespace().next())
+                            .filter(|name| *name != "help")
+                            .map(String::from)
+                            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn commands_match_the_app() {
+        let mut names = subcommands(&[]).unwrap();
+        names.sort();
+        assert_eq!(names, listed(&[]));
+        for (command, _) in crate::COMMANDS.iter() {
+            let mut names = subcommands(&[String::from(*command)]).unwrap_or_default();
+            names.sort();
+            assert_eq!(names, listed(&[command]), "{}", command);
+        }
+    }
+
+    fn session<'a>(config: &'a Config, list_file: &'a Path) -> Session<'a> {
+        Session {
+            name: "shell",
+            refused: &REFUSED,
+            config,
+            list_file,
+            todo_list: TODOList { tasks: Vec::new(), tags: Vec::new() },
+            current: None,
+            changed: vec![list_file.to_path_buf()],
+            messages: Vec::new()
+        }
+    }
+
+    #[test]
+    fn failed_commands_keep_earlier_changes() {
+        let config = Config::default();
+        let list_file = PathBuf::from("unused.json");
+        let mut session = session(&config, &list_file);
+        assert!(session.command(alias::split("add task -n Kept -d ''").unwrap()));
+        assert!(!session.command(alias::split("add task -n Lost -d '' -D 31 -M 2").unwrap()));
+        assert!(!session.command(alias::split("add task -n Lost -d '' -h 25").unwrap()));
+        assert!(session.command(alias::split("modify task Kept -Y 2031 -M 1 -D 31").unwrap()));
+        assert!(session.command(alias::split("modify task Kept -M 2").unwrap()));
+        assert_eq!(session.todo_list.tasks.iter().map(|task| task.name.as_str()).collect::<Vec<_>>(), ["Kept"]);
+        assert_eq!(session.todo_list.tasks[0].due_date.day(), 28);
+        assert_eq!(session.messages.len(), 3);
+    }
+
+    #[test]
+    fn batches_are_applied_all_at_once() {
+        let config = Config::default();
+        let list_file = std::env::temp_dir().join(format!("faros-batch-{}.json", std::process::id()));
+        fs::write(&list_file, "").unwrap();
+        let empty = || TODOList { tasks: Vec::new(), tags: Vec::new() };
+
+        let failed = batch(&config, &list_file, empty(), "add task -n A -d ''\n# A comment\nremove tag foo\nadd task -n B -d '' -D 30 -M 2\n");
+        assert_eq!(failed.unwrap_err().lines().next(), Some("Line 4 failed: add task -n B -d '' -D 30 -M 2"));
+        assert_eq!(fs::read_to_string(&list_file).unwrap(), "");
+
+        batch(&config, &list_file, empty(), "add task -n A -d ''\ncd A\nadd subtask -n B -d ''\n").unwrap();
+        let saved = TODOList::read_from(list_file.to_str().unwrap()).unwrap();
+        fs::remove_file(&list_file).unwrap();
+        assert_eq!(saved.tasks.iter().map(|task| task.name.as_str()).collect::<Vec<_>>(), ["A"]);
+        assert_eq!(saved.tasks[0].children()[0].name, "B");
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(word_start("add task -n \"Buy mi"), 12);
+        assert_eq!(word_start("add task -n Buy\\ mi"), 12);
+        assert_eq!(word_start("cd "), 3);
+        assert_eq!(quote(String::from("Buy \"oat\" milk")), "\"Buy \\\"oat\\\" milk\"");
+        assert_eq!(alias::split(&quote(String::from("a \\ b"))).unwrap(), ["a \\ b"]);
+    }
+}
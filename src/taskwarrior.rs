@@ -0,0 +1,366 @@
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+use crate::{Completion, Priority, TODOList, Task};
+use crate::render;
+
+const DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+#[derive(Deserialize, Serialize)]
+struct Annotation {
+    entry: String,
+    description: String
+}
+
+#[derive(Deserialize)]
+struct Record {
+    uuid: Uuid,
+    description: String,
+    status: String,
+    due: Option<String>,
+    priority: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    project: Option<String>,
+    depends: Option<Value>,
+    #[serde(default)]
+    annotations: Vec<Annotation>,
+    #[serde(flatten)]
+    other: HashMap<String, Value>
+}
+
+#[derive(Serialize)]
+struct ExportRecord {
+    uuid: Uuid,
+    description: String,
+    status: &'static str,
+    due: String,
+    priority: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    depends: Vec<Uuid>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    annotations: Vec<Annotation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    faros_parent: Option<Uuid>
+}
+
+fn parse_date(date: &str) -> Option<DateTime<Local>> {
+    NaiveDateTime::parse_from_str(date, DATE_FORMAT).ok().map(|d| Utc.from_utc_datetime(&d).with_timezone(&Local))
+}
+
+fn depends(value: &Option<Value>) -> Vec<Uuid> {
+    // Taskwarrior 2.6 and later export dependencies as an array of UUIDs, while earlier versions export a comma-separated string.
+    match value {
+        Some(Value::Array(values)) => values.iter().filter_map(|v| v.as_str()).filter_map(|s| Uuid::parse_str(s).ok()).collect(),
+        Some(Value::String(s)) => s.split(',').filter_map(|s| Uuid::parse_str(s.trim()).ok()).collect(),
+        _ => Vec::new()
+    }
+}
+
+pub fn import(contents: &str, todo_list: &mut TODOList, parent_uda: &str) -> Result<Vec<String>, String> {
+    // Adds the tasks in the output of `task export` to the TODO list, keeping their UUIDs. The tree is reconstructed from the parent UDA if a task has one, and otherwise from dependencies, since a faros task can't be completed before its subtasks just as a Taskwarrior task can't be completed before its dependencies. Tasks with neither are placed under tasks named after each part of their project, so that "Work.Reports" becomes a "Reports" task within a "Work" task.
+    let records: Vec<Record> = serde_json::from_str(contents).map_err(|err| format!("The file couldn't be parsed as Taskwarrior JSON: {}", err))?;
+    let mut problems = Vec::new();
+    let existing = todo_list.flattened().iter().map(|t| t.uuid).collect::<HashSet<_>>();
+
+    let mut tasks: HashMap<Uuid, (Task, bool)> = HashMap::new();
+    let mut order = Vec::new();
+    let mut parents: HashMap<Uuid, Uuid> = HashMap::new();
+    let mut projects: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for record in records {
+        match record.status.as_str() {
+            "deleted" => {
+                problems.push(format!("{}: deleted tasks are not imported", record.description));
+                continue;
+            },
+            "recurring" => {
+                problems.push(format!("{}: recurring task templates are not imported, but their pending instances are", record.description));
+                continue;
+            },
+            _ => ()
+        }
+        if existing.contains(&record.uuid) {
+            problems.push(format!("{}: a task with UUID {} is already in your TODO list", record.description, record.uuid));
+            continue;
+        }
+        if tasks.contains_key(&record.uuid) {
+            problems.push(format!("{}: an earlier task in the file has the UUID {}, so it was not imported", record.description, record.uuid));
+            continue;
+        }
+
+        let due_date = match record.due.as_deref().map(|d| (d, parse_date(d))) {
+            Some((_, Some(date))) => date,
+            Some((d, None)) => {
+                problems.push(format!("{}: \"{}\" is not a valid date, so the task was given the default due date", record.description, d));
+                crate::default_due_date()
+            },
+            None => crate::default_due_date()
+        };
+        let priority = match record.priority.as_deref() {
+            Some("H") => Priority::High,
+            Some("L") => Priority::Low,
+            _ => Priority::Medium
+        };
+        let description = record.annotations.iter().map(|a| a.description.as_str()).collect::<Vec<_>>().join("\n");
+        let mut task = Task::new(record.description.clone(), description, priority, due_date);
+        task.uuid = record.uuid;
+        task.tags = record.tags.iter().map(|tag| todo_list.tag_uuid(tag)).collect();
+
+        if let Some(parent) = record.other.get(parent_uda).and_then(|v| v.as_str()).and_then(|s| Uuid::parse_str(s).ok()) {
+            parents.insert(record.uuid, parent);
+        }
+        for dependency in depends(&record.depends) {
+            if let Some(other) = parents.get(&dependency).filter(|&&p| p != record.uuid) {
+                problems.push(format!("{}: {} is already a subtask of {}, so it can't also be a subtask of this task", record.description, dependency, other));
+            } else {
+                parents.entry(dependency).or_insert(record.uuid);
+            }
+        }
+        if let Some(project) = &record.project {
+            projects.insert(record.uuid, project.split('.').map(String::from).collect());
+        }
+        order.push(record.uuid);
+        tasks.insert(record.uuid, (task, record.status == "completed"));
+    }
+
+    // Parents have to be imported tasks, and can't be descendants of their own subtasks.
+    parents.retain(|child, parent| tasks.contains_key(child) && tasks.contains_key(parent));
+    for uuid in &order {
+        // The cycle is broken at the edge which closes it, which may be further up than the task the walk started from.
+        let mut seen = HashSet::from([*uuid]);
+        let mut current = *uuid;
+        while let Some(&parent) = parents.get(&current) {
+            if !seen.insert(parent) {
+                problems.push(format!("{}: its dependencies form a cycle, so it is no longer a subtask of {}", tasks[&current].0.name, tasks[&parent].0.name));
+                parents.remove(&current);
+                break;
+            }
+            current = parent;
+        }
+    }
+    // Complete tasks in faros have no subtasks, so incomplete subtasks of complete tasks have to be moved to the top level.
+    for uuid in &order {
+        if let Some(parent) = parents.get(uuid) {
+            if tasks[parent].1 && !tasks[uuid].1 {
+                problems.push(format!("{}: it is incomplete, but {} is complete, so it was made a top-level task", tasks[uuid].0.name, tasks[parent].0.name));
+                parents.remove(uuid);
+            }
+        }
+    }
+
+    let mut project_tasks: HashMap<Vec<String>, Uuid> = HashMap::new();
+    for uuid in order.clone() {
+        if parents.contains_key(&uuid) {
+            continue;
+        }
+        if let Some(project) = projects.get(&uuid) {
+            for depth in 1..=project.len() {
+                let path = project[..depth].to_vec();
+                if !project_tasks.contains_key(&path) {
+                    let task = Task::new(path[depth - 1].clone(), String::new(), Priority::Medium, crate::default_due_date());
+                    if depth > 1 {
+                        parents.insert(task.uuid, project_tasks[&project[..depth - 1]]);
+                    }
+                    project_tasks.insert(path, task.uuid);
+                    order.push(task.uuid);
+                    tasks.insert(task.uuid, (task, false));
+                }
+            }
+            parents.insert(uuid, project_tasks[project]);
+        }
+    }
+
+    let mut children: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for uuid in &order {
+        if let Some(parent) = parents.get(uuid) {
+            children.entry(*parent).or_default().push(*uuid);
+        }
+    }
+    for uuid in order.iter().filter(|uuid| !parents.contains_key(uuid)) {
+        let task = build(*uuid, &mut tasks, &children, &mut problems);
+        todo_list.tasks.push(task);
+    }
+    Ok(problems)
+}
+
+fn build(uuid: Uuid, tasks: &mut HashMap<Uuid, (Task, bool)>, children: &HashMap<Uuid, Vec<Uuid>>, problems: &mut Vec<String>) -> Task {
+    let (mut task, complete) = tasks.remove(&uuid).unwrap();
+    if complete {
+        // Complete tasks in faros have no subtasks, so the complete subtasks of a complete task can't be kept.
+        drop_subtasks(&task.name, uuid, tasks, children, problems);
+        task.completion = Completion::Complete;
+    } else {
+        task.completion = Completion::Incomplete(children.get(&uuid)
+                                                         .iter()
+                                                         .flat_map(|c| c.iter())
+                                                         .map(|child| build(*child, tasks, children, problems))
+                                                         .collect());
+    }
+    task
+}
+
+fn drop_subtasks(name: &str, uuid: Uuid, tasks: &HashMap<Uuid, (Task, bool)>, children: &HashMap<Uuid, Vec<Uuid>>, problems: &mut Vec<String>) {
+    for child in children.get(&uuid).iter().flat_map(|c| c.iter()) {
+        problems.push(format!("{}: it is a subtask of {}, which is complete, so it was not imported", tasks[child].0.name, name));
+        drop_subtasks(&tasks[child].0.name, *child, tasks, children, problems);
+    }
+}
+
+pub fn export(todo_list: &TODOList) -> String {
+    // Writes every task in a format which `task import` understands. Subtasks become dependencies of their parents, and are also given a faros_parent UDA, so that importing the file into faros again gives back the same tree.
+    let now = Utc::now().format(DATE_FORMAT).to_string();
+    let records = todo_list.flattened().into_iter().map(|task| {
+        let path = todo_list.path(task.uuid);
+        ExportRecord {
+            uuid: task.uuid,
+            description: task.name.clone(),
+            status: match task.completion {
+                Completion::Complete => "completed",
+                Completion::Incomplete(_) => "pending"
+            },
+            due: task.due_date.with_timezone(&Utc).format(DATE_FORMAT).to_string(),
+            priority: match task.priority {
+                Priority::High => "H",
+                Priority::Medium => "M",
+                Priority::Low => "L"
+            },
+            tags: render::tag_names(task, &todo_list.tags).into_iter().map(|tag| tag.replace(' ', "_")).collect(),
+            depends: task.children().iter().map(|child| child.uuid).collect(),
+            annotations: if task.description.is_empty() {
+                Vec::new()
+            } else {
+                vec![Annotation { entry: now.clone(), description: task.description.clone() }]
+            },
+            faros_parent: path.len().checked_sub(2).map(|i| path[i].uuid)
+        }
+    }).collect::<Vec<_>>();
+    let mut json = serde_json::to_string_pretty(&records).unwrap();
+    json.push('\n');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "00000000-0000-4000-8000-00000000000a";
+    const B: &str = "00000000-0000-4000-8000-00000000000b";
+    const C: &str = "00000000-0000-4000-8000-00000000000c";
+    const D: &str = "00000000-0000-4000-8000-00000000000d";
+
+    fn empty() -> TODOList {
+        TODOList { tasks: Vec::new(), tags: Vec::new() }
+    }
+
+    fn record(uuid: &str, name: &str, status: &str, depends: &[&str]) -> Value {
+        serde_json::json!({ "uuid": uuid, "description": name, "status": status, "depends": depends })
+    }
+
+    fn import_records(records: Vec<Value>) -> (TODOList, Vec<String>) {
+        let mut todo_list = empty();
+        let problems = import(&Value::Array(records).to_string(), &mut todo_list, "faros_parent").unwrap();
+        (todo_list, problems)
+    }
+
+    fn names(tasks: &[Task]) -> Vec<&str> {
+        tasks.iter().map(|task| task.name.as_str()).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut todo_list = empty();
+        let mut parent = Task::new(String::from("Parent"), String::from("Notes"), Priority::High, crate::default_due_date());
+        let mut child = Task::new(String::from("Child"), String::new(), Priority::Low, crate::default_due_date());
+        child.completion = Completion::Complete;
+        child.tags = vec![todo_list.tag_uuid("home")];
+        parent.add_subtask(child);
+        todo_list.tasks.push(parent);
+
+        let mut imported = empty();
+        let problems = import(&export(&todo_list), &mut imported, "faros_parent").unwrap();
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(names(&imported.tasks), ["Parent"]);
+        let (parent, original) = (&imported.tasks[0], &todo_list.tasks[0]);
+        assert_eq!(parent.uuid, original.uuid);
+        assert_eq!(parent.description, "Notes");
+        assert!(parent.priority == Priority::High);
+        assert_eq!(parent.due_date.timestamp(), original.due_date.timestamp());
+        assert_eq!(names(parent.children()), ["Child"]);
+        assert!(matches!(parent.children()[0].completion, Completion::Complete));
+        assert_eq!(render::tag_names(&parent.children()[0], &imported.tags), ["home"]);
+    }
+
+    #[test]
+    fn malformed_input() {
+        let mut todo_list = empty();
+        assert!(import("not json", &mut todo_list, "faros_parent").is_err());
+        assert!(import("[{\"description\": \"No UUID\"}]", &mut todo_list, "faros_parent").is_err());
+        assert!(todo_list.tasks.is_empty());
+
+        let (todo_list, problems) = import_records(vec![serde_json::json!({ "uuid": A, "description": "Late", "status": "pending", "due": "tomorrow" })]);
+        assert_eq!(names(&todo_list.tasks), ["Late"]);
+        assert_eq!(problems.len(), 1);
+    }
+
+    #[test]
+    fn dependencies_become_subtasks() {
+        let (todo_list, problems) = import_records(vec![record(A, "A", "pending", &[B]), record(B, "B", "pending", &[])]);
+        assert!(problems.is_empty());
+        assert_eq!(names(&todo_list.tasks), ["A"]);
+        assert_eq!(names(todo_list.tasks[0].children()), ["B"]);
+    }
+
+    #[test]
+    fn cycles_are_broken_at_the_edge_which_closes_them() {
+        // A and B depend on each other, and D is a subtask of A outside the cycle, so the walk from D finds the cycle without D being part of it.
+        let (todo_list, problems) = import_records(vec![
+            record(D, "D", "pending", &[]),
+            record(A, "A", "pending", &[B, D]),
+            record(B, "B", "pending", &[A])
+        ]);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].starts_with("B:"));
+        assert_eq!(names(&todo_list.tasks), ["B"]);
+        let d = todo_list.flattened().into_iter().find(|task| task.name == "D").unwrap();
+        assert_eq!(todo_list.path(d.uuid).iter().map(|task| task.name.as_str()).collect::<Vec<_>>(), ["B", "A", "D"]);
+    }
+
+    #[test]
+    fn subtasks_of_complete_tasks_are_reported() {
+        let (todo_list, problems) = import_records(vec![
+            record(A, "A", "completed", &[B, C]),
+            record(B, "B", "completed", &[D]),
+            record(C, "C", "pending", &[]),
+            record(D, "D", "completed", &[])
+        ]);
+        let mut top = names(&todo_list.tasks);
+        top.sort_unstable();
+        assert_eq!(top, ["A", "C"]);
+        assert!(problems.iter().any(|problem| problem.starts_with("C:")));
+        assert!(problems.iter().any(|problem| problem.starts_with("B:") && problem.contains("not imported")));
+        assert!(problems.iter().any(|problem| problem.starts_with("D:") && problem.contains("not imported")));
+    }
+
+    #[test]
+    fn repeated_uuids() {
+        let (todo_list, problems) = import_records(vec![record(A, "A", "pending", &[B]), record(B, "B", "pending", &[]), record(A, "Again", "pending", &[])]);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].starts_with("Again:"));
+        assert_eq!(names(&todo_list.tasks), ["A"]);
+        assert_eq!(names(todo_list.tasks[0].children()), ["B"]);
+    }
+
+    #[test]
+    fn importing_again_adds_nothing() {
+        let records = Value::Array(vec![record(A, "A", "pending", &[B]), record(B, "B", "pending", &[])]).to_string();
+        let mut todo_list = empty();
+        import(&records, &mut todo_list, "faros_parent").unwrap();
+        let problems = import(&records, &mut todo_list, "faros_parent").unwrap();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert_eq!(todo_list.flattened().len(), 2);
+    }
+}
@@ -0,0 +1,221 @@
+use chrono::NaiveDate;
+use crate::{Completion, Priority, TODOList, Task};
+use crate::render;
+
+pub fn import(contents: &str, todo_list: &mut TODOList) -> Vec<String> {
+    // Adds a top-level task for each line of a todo.txt file, returning a description of everything in the file that couldn't be mapped onto a task. Priorities (A) to (C) become high, medium and low priority, +project and @context become tags, and due:YYYY-MM-DD becomes the due date. Unknown key:value extensions are kept in the task's description, and tasks without a priority are given the default priority.
+    let mut problems = Vec::new();
+    for (i, line) in contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let number = i + 1;
+        let mut words = line.split_whitespace().peekable();
+
+        let complete = words.peek() == Some(&"x");
+        if complete {
+            words.next();
+            // Completed tasks may have a completion date, followed by a creation date.
+            for _ in 0..2 {
+                if words.peek().is_some_and(|w| NaiveDate::parse_from_str(w, "%Y-%m-%d").is_ok()) {
+                    words.next();
+                }
+            }
+        }
+
+        let mut priority = crate::config::defaults().priority;
+        if let Some(p) = words.peek().and_then(|w| w.strip_prefix('(')).and_then(|w| w.strip_suffix(')')).filter(|p| is_priority(p)) {
+            priority = priority_from_letter(p, number, &mut problems);
+            words.next();
+        }
+        if !complete && words.peek().is_some_and(|w| NaiveDate::parse_from_str(w, "%Y-%m-%d").is_ok()) {
+            words.next();
+        }
+
+        let mut name = Vec::new();
+        let mut extensions = Vec::new();
+        let mut tags = Vec::new();
+        let mut due_date = crate::default_due_date();
+        for word in words {
+            match word.split_once(':') {
+                _ if word.len() > 1 && (word.starts_with('+') || word.starts_with('@')) => tags.push(decode_tag(&word[1..])),
+                Some(("due", value)) => match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+                    Ok(date) => due_date = crate::due_on(date),
+                    Err(_) => problems.push(format!("line {}: \"{}\" is not a valid date, so the task was given the default due date", number, value))
+                },
+                Some(("pri", p)) if complete && is_priority(p) => priority = priority_from_letter(p, number, &mut problems),
+                _ if is_extension(word) => {
+                    problems.push(format!("line {}: {} has no equivalent, so it was kept in the task's description", number, word));
+                    extensions.push(word);
+                },
+                _ => name.push(word)
+            }
+        }
+
+        if name.is_empty() {
+            problems.push(format!("line {}: there is no task description, so the line was skipped", number));
+            continue;
+        }
+        let mut task = Task::new(name.join(" "), extensions.join(" "), priority, due_date);
+        task.tags = tags.iter().map(|tag| todo_list.tag_uuid(tag)).collect();
+        if complete {
+            task.completion = Completion::Complete;
+        }
+        todo_list.tasks.push(task);
+    }
+    problems
+}
+
+fn is_extension(word: &str) -> bool {
+    // Whether a word is a key:value extension, rather than part of the task's name such as a time or a URL.
+    word.split_once(':').is_some_and(|(key, value)| !key.is_empty() && !value.is_empty() && !value.starts_with('/'))
+}
+
+fn encode_tag(tag: &str) -> String {
+    // Words are separated by spaces in todo.txt, so spaces in tag names are written as %20, and percent signs as %25 so that they can be told apart.
+    tag.replace('%', "%25").replace(' ', "%20")
+}
+
+fn decode_tag(tag: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = tag;
+    while let Some(i) = rest.find('%') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let (character, length) = match rest.get(1..3) {
+            Some("20") => (' ', 3),
+            Some("25") => ('%', 3),
+            _ => ('%', 1)
+        };
+        decoded.push(character);
+        rest = &rest[length..];
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn is_priority(p: &str) -> bool {
+    p.len() == 1 && p.chars().all(|c| c.is_ascii_uppercase())
+}
+
+fn priority_from_letter(p: &str, number: usize, problems: &mut Vec<String>) -> Priority {
+    match p {
+        "A" => Priority::High,
+        "B" => Priority::Medium,
+        "C" => Priority::Low,
+        _ => {
+            problems.push(format!("line {}: priority ({}) has no equivalent, so it was imported as low priority", number, p));
+            Priority::Low
+        }
+    }
+}
+
+pub fn export(todo_list: &TODOList) -> String {
+    // Writes every task as a line of todo.txt. As todo.txt has no notion of subtasks, the tree is flattened, and as it has no notion of descriptions, they are left out, unless they only hold the key:value extensions an import kept in them, which are written back.
+    let mut lines = Vec::new();
+    for task in todo_list.flattened() {
+        let mut words = Vec::new();
+        let priority = match task.priority {
+            Priority::High => "A",
+            Priority::Medium => "B",
+            Priority::Low => "C"
+        };
+        if let Completion::Complete = task.completion {
+            words.push(String::from("x"));
+        } else {
+            words.push(format!("({})", priority));
+        }
+        words.push(task.name.clone());
+        words.extend(render::tag_names(task, &todo_list.tags).into_iter().map(|tag| format!("+{}", encode_tag(tag))));
+        words.push(format!("due:{}", task.due_date.format("%Y-%m-%d")));
+        let extensions = task.description.split_whitespace().collect::<Vec<_>>();
+        if extensions.iter().all(|word| is_extension(word) && !word.starts_with(['+', '@']) && !word.starts_with("due:") && !word.starts_with("pri:")) {
+            words.extend(extensions.into_iter().map(String::from));
+        }
+        if let Completion::Complete = task.completion {
+            words.push(format!("pri:{}", priority));
+        }
+        lines.push(words.join(" "));
+    }
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty() -> TODOList {
+        TODOList { tasks: Vec::new(), tags: Vec::new() }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut todo_list = empty();
+        let due_date = crate::due_on(NaiveDate::from_ymd(2030, 3, 4));
+        let mut report = Task::new(String::from("Write the report"), String::new(), Priority::High, due_date);
+        report.tags = vec![todo_list.tag_uuid("work"), todo_list.tag_uuid("big project"), todo_list.tag_uuid("100%_done")];
+        report.description = String::from("url:https://example.com id:7");
+        let mut post = Task::new(String::from("Post letters"), String::new(), Priority::Low, due_date);
+        post.completion = Completion::Complete;
+        todo_list.tasks.push(report);
+        todo_list.tasks.push(post);
+
+        let exported = export(&todo_list);
+        let mut imported = empty();
+        let problems = import(&exported, &mut imported);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert_eq!(export(&imported), exported);
+        assert_eq!(imported.tasks.len(), 2);
+        assert_eq!(imported.tasks[0].name, "Write the report");
+        assert!(imported.tasks[0].priority == Priority::High);
+        assert_eq!(render::tag_names(&imported.tasks[0], &imported.tags), ["work", "big project", "100%_done"]);
+        assert_eq!(imported.tasks[0].description, "url:https://example.com id:7");
+        assert!(exported.starts_with("(A) Write the report +work +big%20project +100%25_done due:2030-03-04 url:https://example.com id:7\n"));
+        assert!(imported.tasks[1].priority == Priority::Low);
+        assert!(matches!(imported.tasks[1].completion, Completion::Complete));
+        assert_eq!(imported.tasks[1].due_date, due_date);
+    }
+
+    #[test]
+    fn standard_lines() {
+        let mut todo_list = empty();
+        let problems = import("x 2030-01-02 2030-01-01 (A) Call Mum @phone\n\n2030-01-01 Buy milk +shop due:2030-01-05 url:https://example.com\n", &mut todo_list);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert_eq!(todo_list.tasks[0].name, "Call Mum");
+        assert!(matches!(todo_list.tasks[0].completion, Completion::Complete));
+        assert!(todo_list.tasks[0].priority == Priority::High);
+        assert_eq!(render::tag_names(&todo_list.tasks[0], &todo_list.tags), ["phone"]);
+        assert_eq!(todo_list.tasks[1].name, "Buy milk");
+        assert!(todo_list.tasks[1].priority == crate::config::defaults().priority);
+        assert_eq!(todo_list.tasks[1].description, "url:https://example.com");
+        assert_eq!(todo_list.tasks[1].due_date, crate::due_on(NaiveDate::from_ymd(2030, 1, 5)));
+    }
+
+    #[test]
+    fn descriptions_and_tags() {
+        // Descriptions which aren't extensions are left out, and tags keep the names other programs gave them, unless they look like encoded spaces.
+        let mut todo_list = empty();
+        let mut task = Task::new(String::from("Call Mum"), String::from("Ask about Sunday"), Priority::Medium, crate::default_due_date());
+        task.tags = vec![todo_list.tag_uuid("due:soon")];
+        todo_list.tasks.push(task);
+        assert!(!export(&todo_list).contains("Sunday"));
+        assert_eq!(decode_tag("home_improvement"), "home_improvement");
+        assert_eq!(decode_tag("50%off%20now%"), "50%off now%");
+
+        let mut imported = empty();
+        assert!(import(&export(&todo_list), &mut imported).is_empty());
+        assert_eq!(imported.tasks[0].description, "");
+        assert_eq!(render::tag_names(&imported.tasks[0], &imported.tags), ["due:soon"]);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let mut todo_list = empty();
+        let problems = import("(Z) Odd priority\n(A) due:someday Bad date\n(B) +tag\nx\n", &mut todo_list);
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems[0].starts_with("line 1:"));
+        assert!(problems[1].starts_with("line 2:"));
+        assert!(problems[2].starts_with("line 3:") && problems[3].starts_with("line 4:"));
+        assert_eq!(todo_list.tasks.len(), 2);
+        assert!(todo_list.tasks[0].priority == Priority::Low);
+        assert_eq!(todo_list.tasks[1].name, "Bad date");
+        assert_eq!(todo_list.tasks[1].due_date, crate::default_due_date());
+    }
+}
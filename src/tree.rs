@@ -0,0 +1,51 @@
+use chrono::Local;
+use crate::{Completion, Task};
+use crate::render;
+
+pub struct TreeOptions {
+    pub depth: Option<usize>,
+    pub collapse_completed: bool
+}
+
+pub fn print_tree(roots: &[&Task], keep: &dyn Fn(&Task) -> bool, options: &TreeOptions) {
+    // Prints the given tasks and their subtasks with box-drawing connectors. Tasks are only printed if they, or one of their subtasks, are kept, so that filtered tasks are still shown in the context of their parents.
+    for root in roots.iter().filter(|task| retained(task, keep)) {
+        print_node(root, "", "", 0, keep, options);
+    }
+}
+
+pub fn retained(task: &Task, keep: &dyn Fn(&Task) -> bool) -> bool {
+    keep(task) || task.children().iter().any(|child| retained(child, keep))
+}
+
+fn print_node(task: &Task, connector: &str, prefix: &str, depth: usize, keep: &dyn Fn(&Task) -> bool, options: &TreeOptions) {
+    let children = task.children().iter().filter(|child| retained(child, keep)).collect::<Vec<_>>();
+    let (complete, total) = task.progress();
+    let hidden = !children.is_empty() &&
+        (options.depth.is_some_and(|d| depth >= d) || options.collapse_completed && complete == total);
+
+    println!("{}{}{}{}", prefix, connector, label(task), if hidden { " …" } else { "" });
+    if hidden {
+        return;
+    }
+
+    let prefix = format!("{}{}", prefix, match connector {
+        "├── " => "│   ",
+        "└── " => "    ",
+        _ => ""
+    });
+    for (i, child) in children.iter().enumerate() {
+        let connector = if i + 1 == children.len() { "└── " } else { "├── " };
+        print_node(child, connector, &prefix, depth + 1, keep, options);
+    }
+}
+
+fn label(task: &Task) -> String {
+    let checkbox = match task.completion {
+        Completion::Complete => "[x]",
+        Completion::Incomplete(_) => "[ ]"
+    };
+    let (complete, total) = task.progress();
+    let progress = if total > 0 { format!(", {}/{} done", complete, total) } else { String::new() };
+    format!("{} {} ({}, due {}{})", checkbox, task.name, task.priority, render::relative_due(task.due_date, Local::now()), progress)
+}
@@ -0,0 +1,595 @@
+use chrono::Local;
+use std::collections::HashSet;
+use std::path::Path;
+use ratatui::DefaultTerminal;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use uuid::Uuid;
+use crate::{Completion, TODOList, Task};
+use crate::config::{self, colors};
+use crate::edit;
+use crate::filter::Filter;
+use crate::render;
+
+// The most changes which can be undone, so that long sessions don't keep every version of a large TODO list in memory.
+const UNDO_LIMIT: usize = 100;
+
+const HELP: &str = "j/k move  h/l fold  a add  A subtask  c complete  e edit  m mark  p put under  P put at top  d delete  / filter  u undo  q quit";
+
+enum Mode {
+    Normal,
+    Filter,
+    Add { parent: Option<Uuid>, after: Option<Uuid> },
+    Delete(Uuid)
+}
+
+// What the interface should do after a key has been handled, for the things which need the terminal.
+enum Action {
+    Continue,
+    Edit,
+    Quit
+}
+
+struct Row {
+    uuid: Uuid,
+    depth: usize,
+    has_children: bool
+}
+
+struct App<'a> {
+    todo_list: TODOList,
+    path: &'a Path,
+    rows: Vec<Row>,
+    selected: Option<Uuid>,
+    collapsed: HashSet<Uuid>,
+    filter: Filter,
+    filter_text: String,
+    mode: Mode,
+    input: String,
+    marked: Option<Uuid>,
+    undo: Vec<TODOList>,
+    message: String,
+    color: bool
+}
+
+pub fn run(todo_list: TODOList, path: &Path) -> Result<TODOList, String> {
+    // Browses and changes the TODO list in a full-screen interface until it is closed. Each change is saved as soon as it is made, and the TODO list as it was left is returned, so that it can be saved and committed like any other change.
+    let mut app = App::new(todo_list, path);
+    let mut terminal = ratatui::try_init().map_err(|err| format!("The terminal could not be set up: {}", err))?;
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result.map(|_| app.todo_list)
+}
+
+fn style(style: &config::Style) -> Style {
+    // Translates a style from the configuration file, which is kept as an escape code, into the TUI's styles.
+    let mut result = Style::default();
+    let codes = style.code().trim_start_matches("\x1b[").trim_end_matches('m');
+    for code in codes.split(';').filter_map(|code| code.parse::<u8>().ok()) {
+        result = match code {
+            1 => result.add_modifier(Modifier::BOLD),
+            2 => result.add_modifier(Modifier::DIM),
+            3 => result.add_modifier(Modifier::ITALIC),
+            4 => result.add_modifier(Modifier::UNDERLINED),
+            7 => result.add_modifier(Modifier::REVERSED),
+            30..=37 => result.fg(Color::Indexed(code - 30)),
+            40..=47 => result.bg(Color::Indexed(code - 40)),
+            90..=97 => result.fg(Color::Indexed(code - 82)),
+            100..=107 => result.bg(Color::Indexed(code - 92)),
+            _ => result
+        };
+    }
+    result
+}
+
+fn insert(siblings: &mut Vec<Task>, after: Option<Uuid>, task: Task) {
+    let position = after.and_then(|after| siblings.iter().position(|t| t.uuid == after)).map_or(siblings.len(), |i| i + 1);
+    siblings.insert(position, task);
+}
+
+fn visible(tasks: &[Task], depth: usize, app: &App, rows: &mut Vec<Row>) {
+    // Lists the tasks shown in the tree pane, in order. Tasks which don't match the filter are only shown if one of their subtasks does, and the subtasks of collapsed tasks are hidden.
+    let keep = |task: &Task| app.filter.matches(task, &app.todo_list.tags);
+    for task in tasks.iter().filter(|task| crate::tree::retained(task, &keep)) {
+        let children = task.children().iter().filter(|child| crate::tree::retained(child, &keep)).count();
+        rows.push(Row { uuid: task.uuid, depth, has_children: children > 0 });
+        if !app.collapsed.contains(&task.uuid) {
+            visible(task.children(), depth + 1, app, rows);
+        }
+    }
+}
+
+impl<'a> App<'a> {
+    fn new(todo_list: TODOList, path: &'a Path) -> App<'a> {
+        let mut app = App {
+            todo_list,
+            path,
+            rows: Vec::new(),
+            selected: None,
+            collapsed: HashSet::new(),
+            filter: Filter::default(),
+            filter_text: String::new(),
+            mode: Mode::Normal,
+            input: String::new(),
+            marked: None,
+            undo: Vec::new(),
+            message: String::new(),
+            color: render::use_color()
+        };
+        app.refresh();
+        app
+    }
+}
+
+impl App<'_> {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), String> {
+        loop {
+            terminal.draw(|frame| self.draw(frame)).map_err(|err| format!("The terminal could not be drawn to: {}", err))?;
+            let key = match event::read().map_err(|err| format!("The terminal could not be read from: {}", err))? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue
+            };
+            match self.key(key) {
+                Action::Continue => (),
+                Action::Edit => {
+                    // The editor needs the terminal, so the interface is put away until it is closed.
+                    ratatui::restore();
+                    self.edit();
+                    *terminal = ratatui::try_init().map_err(|err| format!("The terminal could not be set up: {}", err))?;
+                },
+                Action::Quit => return Ok(())
+            }
+        }
+    }
+
+    fn key(&mut self, key: KeyEvent) -> Action {
+        // Handles a key press, leaving anything which needs the terminal to the caller.
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Action::Quit;
+        }
+        self.message.clear();
+        match self.mode {
+            Mode::Normal => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+                KeyCode::Char('e') => return Action::Edit,
+                code => self.normal(code)
+            },
+            Mode::Filter => self.type_filter(key.code),
+            Mode::Add { parent, after } => self.type_name(key.code, parent, after),
+            Mode::Delete(uuid) => {
+                if key.code == KeyCode::Char('y') {
+                    self.delete(uuid);
+                } else {
+                    self.message = String::from("Nothing was deleted.");
+                }
+                self.mode = Mode::Normal;
+            }
+        }
+        Action::Continue
+    }
+
+    fn refresh(&mut self) {
+        // Works out the rows of the tree pane again after anything changes, keeping the same task selected if it is still shown, or otherwise the task now in its place.
+        let index = self.index();
+        let mut rows = Vec::new();
+        visible(&self.todo_list.tasks, 0, self, &mut rows);
+        self.rows = rows;
+        if self.selected.is_none_or(|uuid| !self.rows.iter().any(|row| row.uuid == uuid)) {
+            self.selected = self.rows.get(index.min(self.rows.len().saturating_sub(1))).map(|row| row.uuid);
+        }
+    }
+
+    fn index(&self) -> usize {
+        self.selected.and_then(|uuid| self.rows.iter().position(|row| row.uuid == uuid)).unwrap_or(0)
+    }
+
+    fn task(&self, uuid: Uuid) -> Option<&Task> {
+        self.todo_list.path(uuid).pop()
+    }
+
+    fn change(&mut self, change: impl FnOnce(&mut TODOList)) {
+        // Makes a change which can be undone, and saves it straight away.
+        self.undo.push(self.todo_list.clone());
+        if self.undo.len() > UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+        change(&mut self.todo_list);
+        self.save();
+    }
+
+    fn save(&mut self) {
+        if let Err(err) = self.todo_list.write_to(&self.path.to_string_lossy()) {
+            self.message = err;
+        }
+        self.refresh();
+    }
+
+    fn normal(&mut self, code: KeyCode) {
+        let index = self.index();
+        let selected = self.selected.and_then(|uuid| self.task(uuid));
+        let name = selected.map(|task| task.name.clone()).unwrap_or_default();
+        match (code, self.selected) {
+            (KeyCode::Char('j'), _) | (KeyCode::Down, _) => {
+                self.selected = self.rows.get(index + 1).or(self.rows.get(index)).map(|row| row.uuid);
+            },
+            (KeyCode::Char('k'), _) | (KeyCode::Up, _) => {
+                self.selected = self.rows.get(index.saturating_sub(1)).map(|row| row.uuid);
+            },
+            (KeyCode::Char('g'), _) | (KeyCode::Home, _) => self.selected = self.rows.first().map(|row| row.uuid),
+            (KeyCode::Char('G'), _) | (KeyCode::End, _) => self.selected = self.rows.last().map(|row| row.uuid),
+            (KeyCode::Char('h'), Some(uuid)) | (KeyCode::Left, Some(uuid)) => {
+                // Collapses the selected task, or if it is already collapsed, moves up to its parent.
+                if self.rows[index].has_children && self.collapsed.insert(uuid) {
+                    self.refresh();
+                } else {
+                    let path = self.todo_list.path(uuid);
+                    self.selected = path.len().checked_sub(2).map(|i| path[i].uuid).or(Some(uuid));
+                }
+            },
+            (KeyCode::Char('l'), Some(uuid)) | (KeyCode::Right, Some(uuid)) => {
+                self.collapsed.remove(&uuid);
+                self.refresh();
+            },
+            (KeyCode::Enter, Some(uuid)) | (KeyCode::Char(' '), Some(uuid)) => {
+                if !self.collapsed.remove(&uuid) {
+                    self.collapsed.insert(uuid);
+                }
+                self.refresh();
+            },
+            (KeyCode::Char('a'), selected) => {
+                let parent = selected.and_then(|uuid| {
+                    let path = self.todo_list.path(uuid);
+                    path.len().checked_sub(2).map(|i| path[i].uuid)
+                });
+                self.mode = Mode::Add { parent, after: selected };
+                self.input.clear();
+            },
+            (KeyCode::Char('A'), Some(uuid)) => {
+                self.mode = Mode::Add { parent: Some(uuid), after: None };
+                self.input.clear();
+            },
+            (KeyCode::Char('c'), Some(uuid)) => {
+                let task = self.task(uuid).unwrap();
+                if matches!(task.completion, Completion::Complete) {
+                    self.message = format!("{} is already complete.", name);
+                } else if task.children().iter().any(|child| matches!(child.completion, Completion::Incomplete(_))) {
+                    self.message = format!("{} can't be completed, as it has incomplete subtask(s).", name);
+                } else {
+                    self.change(|todo_list| todo_list.task_from_uuid(uuid).completion = Completion::Complete);
+                    self.message = format!("Completed {}.", name);
+                }
+            },
+            (KeyCode::Char('d'), Some(uuid)) => {
+                let subtasks = self.task(uuid).unwrap().flattened().len() - 1;
+                self.message = if subtasks > 0 {
+                    format!("Delete {} and its {} subtask(s)? (y/n)", name, subtasks)
+                } else {
+                    format!("Delete {}? (y/n)", name)
+                };
+                self.mode = Mode::Delete(uuid);
+            },
+            (KeyCode::Char('m'), Some(uuid)) => {
+                if self.marked == Some(uuid) {
+                    self.marked = None;
+                    self.message = format!("Unmarked {}.", name);
+                } else {
+                    self.marked = Some(uuid);
+                    self.message = format!("Marked {}. Select a task and press p to move it there, or P to move it to the top level.", name);
+                }
+            },
+            (KeyCode::Char('p'), Some(uuid)) => self.put(Some(uuid)),
+            (KeyCode::Char('P'), _) => self.put(None),
+            (KeyCode::Char('/'), _) => {
+                self.mode = Mode::Filter;
+                self.input = self.filter_text.clone();
+            },
+            (KeyCode::Char('u'), _) => match self.undo.pop() {
+                Some(todo_list) => {
+                    self.todo_list = todo_list;
+                    self.save();
+                    self.message = String::from("Undid the last change.");
+                },
+                None => self.message = String::from("There is nothing to undo.")
+            },
+            (KeyCode::Char('?'), _) => self.message = String::from(HELP),
+            _ => ()
+        }
+    }
+
+    fn type_filter(&mut self, code: KeyCode) {
+        // Filters the tree as the filter is typed, using the same expressions as views. Expressions which can't be read yet, such as "priority:hi", leave the last filter in place.
+        match code {
+            KeyCode::Enter => self.mode = Mode::Normal,
+            KeyCode::Esc => {
+                self.input.clear();
+                self.mode = Mode::Normal;
+            },
+            KeyCode::Backspace => {
+                self.input.pop();
+            },
+            KeyCode::Char(c) => self.input.push(c),
+            _ => return
+        }
+        match Filter::parse(&self.input) {
+            Ok(filter) => {
+                self.filter = filter;
+                self.filter_text = self.input.clone();
+                self.refresh();
+            },
+            Err(err) => self.message = err
+        }
+    }
+
+    fn type_name(&mut self, code: KeyCode, parent: Option<Uuid>, after: Option<Uuid>) {
+        match code {
+            KeyCode::Esc => self.mode = Mode::Normal,
+            KeyCode::Backspace => {
+                self.input.pop();
+            },
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Enter if self.input.trim().is_empty() => self.mode = Mode::Normal,
+            KeyCode::Enter => {
+                // New tasks are added after the selected task, or at the end of the task they are added to, with the default due date and priority.
+                let task = Task::new(String::from(self.input.trim()), String::new(), config::defaults().priority, crate::default_due_date());
+                let uuid = task.uuid;
+                self.change(|todo_list| match parent {
+                    Some(parent) => {
+                        let parent = todo_list.task_from_uuid(parent);
+                        match &mut parent.completion {
+                            Completion::Incomplete(children) => insert(children, after, task),
+                            Completion::Complete => parent.add_subtask(task)
+                        }
+                    },
+                    None => insert(&mut todo_list.tasks, after, task)
+                });
+                if let Some(parent) = parent {
+                    self.collapsed.remove(&parent);
+                }
+                self.message = format!("Added {}.", self.input.trim());
+                self.selected = Some(uuid);
+                self.refresh();
+                self.mode = Mode::Normal;
+            },
+            _ => ()
+        }
+    }
+
+    fn delete(&mut self, uuid: Uuid) {
+        let name = self.task(uuid).map(|task| task.name.clone()).unwrap_or_default();
+        self.change(|todo_list| todo_list.remove_uuid(uuid));
+        if self.marked == Some(uuid) {
+            self.marked = None;
+        }
+        self.message = format!("Deleted {}. Press u to undo.", name);
+    }
+
+    fn put(&mut self, parent: Option<Uuid>) {
+        // Moves the marked task, along with its subtasks, to the end of the selected task's subtasks, or to the top level. A task can't be moved into its own subtasks.
+        let marked = match self.marked.filter(|uuid| self.task(*uuid).is_some()) {
+            Some(marked) => marked,
+            None => {
+                self.message = String::from("Mark a task to move with m first.");
+                return;
+            }
+        };
+        if parent.is_some_and(|parent| self.todo_list.path(parent).iter().any(|task| task.uuid == marked)) {
+            self.message = String::from("A task can't be moved into itself or its own subtasks.");
+            return;
+        }
+        let task = self.task(marked).unwrap().clone();
+        let name = task.name.clone();
+        self.change(|todo_list| {
+            todo_list.remove_uuid(marked);
+            match parent {
+                Some(parent) => todo_list.task_from_uuid(parent).add_subtask(task),
+                None => todo_list.tasks.push(task)
+            }
+        });
+        if let Some(parent) = parent {
+            self.collapsed.remove(&parent);
+        }
+        self.marked = None;
+        self.selected = Some(marked);
+        self.refresh();
+        self.message = match parent.and_then(|parent| self.task(parent)) {
+            Some(parent) => format!("Moved {} under {}.", name, parent.name),
+            None => format!("Moved {} to the top level.", name)
+        };
+    }
+
+    fn edit(&mut self) {
+        let uuid = match self.selected {
+            Some(uuid) => uuid,
+            None => return
+        };
+        match edit::edit(self.task(uuid).unwrap(), &self.todo_list) {
+            Ok(Some(fields)) => {
+                self.change(|todo_list| edit::apply(fields, uuid, todo_list));
+                self.message = String::from("Saved your changes.");
+            },
+            Ok(None) => self.message = String::from("The file was emptied, so the task was left as it was."),
+            Err(err) => self.message = err
+        }
+    }
+
+    fn draw(&self, frame: &mut ratatui::Frame) {
+        let [main, status] = Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [tree, detail] = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(main);
+        let paint = |style: Style| if self.color { style } else { Style::default() };
+
+        let now = Local::now();
+        let items = self.rows.iter().map(|row| {
+            let task = self.task(row.uuid).unwrap();
+            let fold = match (row.has_children, self.collapsed.contains(&row.uuid)) {
+                (false, _) => "  ",
+                (true, true) => "▸ ",
+                (true, false) => "▾ "
+            };
+            let (checkbox, name_style) = match task.completion {
+                Completion::Complete => ("[x] ", Style::default().add_modifier(Modifier::DIM | Modifier::CROSSED_OUT)),
+                Completion::Incomplete(_) => ("[ ] ", Style::default())
+            };
+            let due_style = match task.completion {
+                Completion::Incomplete(_) if task.due_date < now => paint(style(&colors().overdue)),
+                _ => Style::default().add_modifier(Modifier::DIM)
+            };
+            let priority_style = paint(style(match task.priority {
+                crate::Priority::High => &colors().high,
+                crate::Priority::Medium => &colors().medium,
+                crate::Priority::Low => &colors().low
+            }));
+            let mut spans = vec![
+                Span::raw(format!("{}{}{}", "  ".repeat(row.depth), fold, checkbox)),
+                Span::styled(task.name.clone(), name_style),
+                Span::styled(format!(" {}", task.priority), priority_style),
+                Span::styled(format!(" {}", render::relative_due(task.due_date, now)), due_style)
+            ];
+            if self.marked == Some(row.uuid) {
+                spans.push(Span::styled(" (marked)", Style::default().add_modifier(Modifier::BOLD)));
+            }
+            ListItem::new(Line::from(spans))
+        }).collect::<Vec<_>>();
+        let title = if self.filter_text.is_empty() { String::from(" Tasks ") } else { format!(" Tasks matching {} ", self.filter_text) };
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default().with_selected(self.selected.map(|_| self.index()));
+        frame.render_stateful_widget(list, tree, &mut state);
+
+        let details = match self.selected.and_then(|uuid| self.task(uuid)) {
+            Some(task) => crate::details(task, &self.todo_list).into_iter().map(Line::from).collect::<Vec<_>>(),
+            None => vec![Line::from("There are no tasks to show. Press a to add one.")]
+        };
+        frame.render_widget(Paragraph::new(details).wrap(Wrap { trim: false }).block(Block::bordered().title(" Details ")), detail);
+
+        let line = self.status_line();
+        if matches!(self.mode, Mode::Filter | Mode::Add { .. }) {
+            frame.set_cursor_position((Line::from(line.as_str()).width().min(status.width.saturating_sub(1) as usize) as u16, status.y));
+        }
+        frame.render_widget(Paragraph::new(line), status);
+    }
+
+    fn status_line(&self) -> String {
+        match self.mode {
+            Mode::Filter => format!("/{}", self.input),
+            Mode::Add { parent: Some(parent), after: None } => {
+                format!("New subtask of {}: {}", self.task(parent).map(|task| task.name.as_str()).unwrap_or_default(), self.input)
+            },
+            Mode::Add { .. } => format!("New task: {}", self.input),
+            _ if !self.message.is_empty() => self.message.clone(),
+            _ => String::from("Press ? for help, or q to quit.")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::Priority;
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("faros-tui-{}-{}.json", name, std::process::id()))
+    }
+
+    fn app(path: &Path) -> App<'_> {
+        let task = |name: &str| Task::new(String::from(name), String::new(), Priority::Medium, crate::default_due_date());
+        let mut write = task("Write report");
+        write.add_subtask(task("Draft"));
+        App::new(TODOList { tasks: vec![write, task("Post letters")], tags: Vec::new() }, path)
+    }
+
+    fn press(app: &mut App, keys: &str) {
+        for c in keys.chars() {
+            let code = match c {
+                '\n' => KeyCode::Enter,
+                '\x1b' => KeyCode::Esc,
+                '\x08' => KeyCode::Backspace,
+                c => KeyCode::Char(c)
+            };
+            app.key(KeyEvent::from(code));
+        }
+    }
+
+    fn names(app: &App) -> Vec<String> {
+        app.rows.iter().map(|row| format!("{}{}", "  ".repeat(row.depth), app.task(row.uuid).unwrap().name)).collect()
+    }
+
+    #[test]
+    fn undo_limit() {
+        let path = path("undo");
+        let mut app = app(&path);
+        for _ in 0..UNDO_LIMIT + 5 {
+            press(&mut app, "aTask\n");
+        }
+        assert_eq!(app.undo.len(), UNDO_LIMIT);
+        assert_eq!(app.todo_list.tasks.len(), UNDO_LIMIT + 7);
+
+        // Only the last changes can be undone, and each one undone is saved.
+        press(&mut app, &"u".repeat(UNDO_LIMIT));
+        assert_eq!(app.message, "Undid the last change.");
+        assert_eq!(app.todo_list.tasks.len(), 7);
+        assert_eq!(TODOList::read_from(&path.to_string_lossy()).unwrap().tasks.len(), 7);
+        press(&mut app, "u");
+        assert_eq!(app.message, "There is nothing to undo.");
+        assert_eq!(app.todo_list.tasks.len(), 7);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn put_after_mark() {
+        let path = path("put");
+        let mut app = app(&path);
+        press(&mut app, "p");
+        assert_eq!(app.message, "Mark a task to move with m first.");
+
+        // Tasks can't be put under themselves or their subtasks.
+        press(&mut app, "mjp");
+        assert_eq!(app.message, "A task can't be moved into itself or its own subtasks.");
+        assert!(app.undo.is_empty());
+
+        press(&mut app, "Gp");
+        assert_eq!(app.message, "Moved Write report under Post letters.");
+        assert_eq!(names(&app), ["Post letters", "  Write report", "    Draft"]);
+        assert_eq!(app.selected, Some(app.rows[1].uuid));
+        assert_eq!(app.marked, None);
+
+        press(&mut app, "p");
+        assert_eq!(app.message, "Mark a task to move with m first.");
+        press(&mut app, "GmP");
+        assert_eq!(app.message, "Moved Draft to the top level.");
+        assert_eq!(names(&app), ["Post letters", "  Write report", "Draft"]);
+        press(&mut app, "uu");
+        assert_eq!(names(&app), ["Write report", "  Draft", "Post letters"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn filter_reset() {
+        let path = path("filter");
+        let mut app = app(&path);
+        press(&mut app, "/draft");
+        assert_eq!(names(&app), ["Write report", "  Draft"]);
+        assert_eq!(app.status_line(), "/draft");
+
+        // Enter keeps the filter, which is shown when it is opened again, and Escape clears it.
+        press(&mut app, "\n");
+        assert_eq!(app.filter_text, "draft");
+        press(&mut app, "/");
+        assert_eq!(app.input, "draft");
+        press(&mut app, "\x1b");
+        assert_eq!(app.filter_text, "");
+        assert_eq!(names(&app), ["Write report", "  Draft", "Post letters"]);
+        assert!(matches!(app.mode, Mode::Normal));
+
+        // Keys typed into the filter aren't taken as commands.
+        press(&mut app, "/q\x08\x08\n");
+        assert!(matches!(app.mode, Mode::Normal));
+        assert_eq!(names(&app).len(), 3);
+        assert!(!path.exists());
+    }
+}